@@ -0,0 +1,150 @@
+use super::prim::Prim;
+use super::rpkg::Entry;
+use super::{log, GameVersion, PrimPort};
+use std::path::{Path, PathBuf};
+
+/// Output path template used when none is given: a folder named after the output version next
+/// to each input PRIM.
+pub const DEFAULT_TEMPLATE: &str = "{dir}/{version}/{name}";
+
+/// The given PRIM files, and the PRIM files directly inside the given folders.
+pub fn collect_inputs(paths: &[PathBuf]) -> Vec<PathBuf> {
+    let is_prim = |path: &Path| {
+        path.extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("prim"))
+    };
+    let mut inputs = Vec::new();
+    for path in paths {
+        if !path.is_dir() {
+            inputs.push(path.clone());
+            continue;
+        }
+        let Ok(entries) = std::fs::read_dir(path) else {
+            log!("Warning: Cannot read folder {}", path.to_str().unwrap());
+            continue;
+        };
+        let mut prims: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.is_file() && is_prim(path))
+            .collect();
+        prims.sort();
+        inputs.extend(prims);
+    }
+    inputs
+}
+
+/// Output path of an input PRIM from a template. `{dir}` is the folder of the input, `{name}` its
+/// file name, `{stem}` its file name without extension and `{version}` the output version.
+pub fn output_path(template: &str, input: &Path, output_version: &GameVersion) -> PathBuf {
+    let dir = input.parent().unwrap_or(Path::new("")).to_str().unwrap();
+    let dir = if dir.is_empty() { "." } else { dir };
+    let version: String = (*output_version).try_into().unwrap();
+    PathBuf::from(
+        template
+            .replace("{dir}", dir)
+            .replace("{name}", input.file_name().unwrap().to_str().unwrap())
+            .replace("{stem}", input.file_stem().unwrap().to_str().unwrap())
+            .replace("{version}", &version.to_lowercase()),
+    )
+}
+
+/// Game version of a PRIM file from its layout. HM2016 and WOA share a layout and are told apart
+/// by the reference flags of the meta file, files without one are read as WOA.
+pub fn detect_version(path: &Path) -> Option<GameVersion> {
+    let buffer = std::fs::read(path).ok()?;
+    match Prim::detect_version(&buffer)? {
+        GameVersion::Woa => match Entry::read_meta_for(path) {
+            Ok(Some((meta, _))) if meta.has_legacy_references() => Some(GameVersion::Hm2016),
+            _ => Some(GameVersion::Woa),
+        },
+        version => Some(version),
+    }
+}
+
+/// Ports one PRIM, creating the folder of its output path first.
+pub fn port(mut prim_port: PrimPort) -> Result<(), String> {
+    if let Some(dir) = prim_port.output_prim_path.parent() {
+        if let Err(err) = std::fs::create_dir_all(dir) {
+            let message = format!("Error creating folder {}: {}", dir.to_str().unwrap(), err);
+            log!("{}", message);
+            return Err(message);
+        }
+    }
+    prim_port.port().map_err(|err| err.message)
+}
+
+/// The port of one input of a batch: `base` with the input, the versions and the output path from
+/// the template. The input version is detected when not given. Inputs are files, so the RPKG
+/// options of `base` are dropped: they would look up each path as a resource and write every
+/// output into the same patch archive or under the same resource.
+pub fn prim_port(
+    base: &PrimPort,
+    input: &Path,
+    input_version: Option<GameVersion>,
+    output_version: GameVersion,
+    template: &str,
+) -> Result<PrimPort, String> {
+    let input_version = match input_version.or_else(|| detect_version(input)) {
+        Some(version) => version,
+        None => {
+            return Err(String::from(
+                "Error: Cannot detect the game version of the input PRIM, select it manually",
+            ))
+        }
+    };
+    let mut prim_port = base.clone();
+    prim_port.input_prim_path = input.to_path_buf();
+    prim_port.output_prim_path = output_path(template, input, &output_version);
+    prim_port.input_version = input_version;
+    prim_port.output_version = output_version;
+    prim_port.use_gui = false;
+    prim_port.rpkg_path = None;
+    prim_port.patch_rpkg_path = None;
+    prim_port.resource = None;
+    Ok(prim_port)
+}
+
+/// Ports every input of a batch in turn, keeping going after failed files.
+pub fn run(
+    base: &PrimPort,
+    inputs: &[PathBuf],
+    input_version: Option<GameVersion>,
+    output_version: GameVersion,
+    template: &str,
+) -> log::Result<()> {
+    let inputs = collect_inputs(inputs);
+    if inputs.is_empty() {
+        return Err(log::error(String::from("Error: No PRIM files found")));
+    }
+    let mut failures = Vec::new();
+    for input in &inputs {
+        // Errors of the port itself were printed while porting.
+        let result = match prim_port(base, input, input_version, output_version, template) {
+            Ok(prim_port) => port(prim_port),
+            Err(message) => {
+                log!("{}", message);
+                Err(message)
+            }
+        };
+        if let Err(message) = result {
+            failures.push((input, message));
+        }
+        log!();
+    }
+    log!(
+        "Ported {} of {} files",
+        inputs.len() - failures.len(),
+        inputs.len()
+    );
+    if failures.is_empty() {
+        return Ok(());
+    }
+    log!("Failed files:");
+    for (input, message) in &failures {
+        log!("  {}: {}", input.to_str().unwrap(), message);
+    }
+    Err(log::error(format!(
+        "Error: Failed to port {} files",
+        failures.len()
+    )))
+}
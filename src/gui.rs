@@ -1,35 +1,105 @@
-use super::{GameVersion, PrimPort};
+use super::prim::Prim;
+use super::{batch, log, GameVersion, PrimPort};
 use eframe::egui;
 use egui::{FontFamily, FontId, TextStyle};
-use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+mod hexview;
+mod options;
+mod preview;
+mod queue;
+mod worker;
+use hexview::HexView;
+use options::Options;
+use preview::Preview;
+use queue::{version_combo, Job, Queue, Status};
+use worker::{Failure, Output, Worker};
+
+#[derive(Default, PartialEq)]
+enum Tab {
+    #[default]
+    Preview,
+    Inspector,
+    HexView,
+    Queue,
+}
+
+/// Number of input PRIM files kept in the recent files list.
+const MAX_RECENT_FILES: usize = 10;
+
+/// The GUI state restored on the next start.
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+struct Settings {
+    input_version: GameVersion,
+    output_version: GameVersion,
+    profile_path: String,
+    input_dir: Option<PathBuf>,
+    output_dir: Option<PathBuf>,
+    recent_files: Vec<PathBuf>,
+    template: String,
+    options: Options,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            input_version: GameVersion::Woa,
+            output_version: GameVersion::Woa,
+            profile_path: String::new(),
+            input_dir: None,
+            output_dir: None,
+            recent_files: Vec::new(),
+            template: batch::DEFAULT_TEMPLATE.to_string(),
+            options: Options::default(),
+        }
+    }
+}
 
 #[derive(Default)]
 pub struct MyApp {
     prim_port: PrimPort,
     input_prim_path: String,
     output_prim_path: String,
-    input_version: usize,
-    output_version: usize,
-    game_versions: Vec<String>,
+    settings: Settings,
     window_open: bool,
+    window_message: &'static str,
+    preview: Preview,
+    hex_view: HexView,
+    tab: Tab,
+    inspected: Option<Arc<Mutex<Prim>>>,
+    inspected_changed: bool,
+    queue: Queue,
+    worker: Worker,
+    failure: Option<Failure>,
 }
 
 impl MyApp {
     pub fn new(cc: &eframe::CreationContext<'_>, prim_port: PrimPort) -> Self {
         configure_text_styles(&cc.egui_ctx);
-        let mut game_versions = Vec::new();
-        game_versions.push("HMA".to_string());
-        game_versions.push("ALPHA".to_string());
-        game_versions.push("HM2016".to_string());
-        game_versions.push("WOA".to_string());
+        let settings: Settings = cc
+            .storage
+            .and_then(|storage| eframe::get_value(storage, eframe::APP_KEY))
+            .unwrap_or_default();
+        let mut queue = Queue::default();
+        queue.template = settings.template.clone();
         Self {
-            prim_port: prim_port,
+            prim_port,
             input_prim_path: String::new(),
             output_prim_path: String::new(),
-            input_version: 3,
-            output_version: 3,
-            game_versions: game_versions,
+            settings,
             window_open: false,
+            window_message: "",
+            preview: Preview::default(),
+            hex_view: HexView::default(),
+            tab: Tab::Preview,
+            inspected: None,
+            inspected_changed: false,
+            queue,
+            worker: Worker::default(),
+            failure: None,
         }
     }
 }
@@ -58,14 +128,68 @@ fn configure_text_styles(ctx: &egui::Context) {
 }
 
 impl eframe::App for MyApp {
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        self.settings.template = self.queue.template.clone();
+        eframe::set_value(storage, eframe::APP_KEY, &self.settings);
+    }
+
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        match self.worker.poll() {
+            Some(Ok(output)) => self.finish(output),
+            Some(Err(failure)) => self.failure = Some(failure),
+            None => (),
+        }
+        self.queue.poll();
+        if self.worker.is_running() {
+            ctx.request_repaint();
+        }
+
+        let dropped: Vec<PathBuf> = ctx.input(|input| {
+            input
+                .raw
+                .dropped_files
+                .iter()
+                .filter_map(|file| file.path.clone())
+                .collect()
+        });
+        if !dropped.is_empty() && !self.worker.is_running() {
+            self.queue.add(&dropped);
+            self.tab = Tab::Queue;
+        }
+
+        if let Some(failure) = &self.failure {
+            let mut close = false;
+            egui::Window::new(format!("{} failed", failure.task))
+                .collapsible(false)
+                .resizable(false)
+                .fixed_pos((150.0, 130.0))
+                .show(ctx, |ui| {
+                    if let Some(file) = &failure.file {
+                        ui.label(format!("File: {}", file.display()));
+                    }
+                    if let Some(offset) = failure.offset {
+                        ui.label(format!("Offset: {:#x}", offset));
+                    }
+                    ui.label(&failure.message);
+                    ui.add_space(20.0);
+                    ui.vertical_centered(|ui| {
+                        close = ui
+                            .add_sized([60.0, 30.0], egui::Button::new("Ok"))
+                            .clicked();
+                    });
+                });
+            if close {
+                self.failure = None;
+            }
+        }
+
         if self.window_open {
             egui::Window::new("")
                 .title_bar(false)
                 .fixed_pos((150.0, 130.0))
                 .show(ctx, |ui| {
                     ui.vertical_centered(|ui| {
-                        ui.label("PRIM Successfully Ported!");
+                        ui.label(self.window_message);
                         ui.add_space(20.0);
                         let button = ui.add_sized([60.0, 30.0], egui::Button::new("Ok"));
                         if button.clicked() {
@@ -75,89 +199,344 @@ impl eframe::App for MyApp {
                 });
         }
 
-        egui::CentralPanel::default().show(ctx, |ui| {
-            ui.set_enabled(!self.window_open);
-            ui.vertical_centered(|ui| {
-                ui.heading("PrimPort");
-                ui.label("Select input and output files and click Port!");
-                ui.label("For CLI Usage: primport.exe <input_prim> <input_version> <output_version> <output_prim>");
-            });
-
-            ui.separator();
+        let enabled = !self.window_open && self.failure.is_none();
 
-            ui.horizontal(|ui| {
-                if ui.button("Select Input PRIM file…").clicked() {
-                    if let Some(path) = rfd::FileDialog::new().pick_file() {
-                        self.input_prim_path = path.display().to_string();
+        egui::TopBottomPanel::bottom("Log")
+            .resizable(true)
+            .default_height(140.0)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Log");
+                    if ui.small_button("Clear").clicked() {
+                        self.worker.clear_log();
                     }
-                }
-                ui.add_space(224.0);
-                ui.label("Game Version:");
-                egui::ComboBox::from_id_source("Input Game Version")
-                    .selected_text(format!("{}", &self.game_versions[self.input_version]))
-                    .show_ui(ui, |ui| {
-                        for i in 0..self.game_versions.len() {
-                            let value = ui.selectable_value(
-                                &mut &self.game_versions[i],
-                                &self.game_versions[self.input_version],
-                                &self.game_versions[i],
-                            );
-                            if value.clicked() {
-                                self.input_version = i;
-                            }
+                });
+                egui::ScrollArea::vertical()
+                    .auto_shrink([false, false])
+                    .stick_to_bottom(true)
+                    .show(ui, |ui| {
+                        for line in self.worker.log() {
+                            ui.monospace(line);
                         }
                     });
             });
-            ui.label("Input PRIM file:");
-            ui.add_sized(
-                [640.0, 0.0],
-                egui::TextEdit::singleline(&mut self.input_prim_path),
-            );
 
-            ui.add_space(10.0);
+        egui::SidePanel::left("Port")
+            .resizable(false)
+            .exact_width(656.0)
+            .show(ctx, |ui| {
+                ui.set_enabled(enabled && !self.worker.is_running());
+                ui.vertical_centered(|ui| {
+                    ui.heading("PrimPort");
+                    ui.label("Select input and output files and click Port!");
+                    ui.label("For CLI Usage: primport.exe <input_prim> <input_version> <output_version> <output_prim>");
+                });
 
-            ui.horizontal(|ui| {
-                if ui.button("Select Output PRIM file…").clicked() {
-                    if let Some(path) = rfd::FileDialog::new().save_file() {
-                        self.output_prim_path = path.display().to_string();
+                ui.separator();
+
+                ui.horizontal(|ui| {
+                    if ui.button("Select Input PRIM file…").clicked() {
+                        if let Some(path) = dialog(&self.settings.input_dir).pick_file() {
+                            self.settings.input_dir = path.parent().map(Path::to_path_buf);
+                            self.input_prim_path = path.display().to_string();
+                        }
                     }
-                }
-                ui.add_space(210.0);
-                ui.label("Game Version:");
-                egui::ComboBox::from_id_source("Output Game Version")
-                    .selected_text(format!("{}", &self.game_versions[self.output_version]))
-                    .show_ui(ui, |ui| {
-                        for i in 0..self.game_versions.len() {
-                            let value = ui.selectable_value(
-                                &mut &self.game_versions[i],
-                                &self.game_versions[self.output_version],
-                                &self.game_versions[i],
-                            );
-                            if value.clicked() {
-                                self.output_version = i;
+                    ui.add_enabled_ui(!self.settings.recent_files.is_empty(), |ui| {
+                        ui.menu_button("Recent", |ui| {
+                            for path in &self.settings.recent_files {
+                                if ui.button(path.display().to_string()).clicked() {
+                                    self.input_prim_path = path.display().to_string();
+                                    ui.close_menu();
+                                }
                             }
-                        }
+                        });
                     });
-            });
-            ui.label("Output PRIM file:");
-            ui.add_sized(
-                [640.0, 0.0],
-                egui::TextEdit::singleline(&mut self.output_prim_path),
-            );
+                    ui.add_space(160.0);
+                    ui.label("Game Version:");
+                    version_combo(ui, "Input Game Version", &mut self.settings.input_version);
+                });
+                ui.label("Input PRIM file:");
+                ui.add_sized(
+                    [640.0, 0.0],
+                    egui::TextEdit::singleline(&mut self.input_prim_path),
+                );
 
-            ui.separator();
+                ui.add_space(10.0);
+
+                ui.horizontal(|ui| {
+                    if ui.button("Select Output PRIM file…").clicked() {
+                        if let Some(path) = dialog(&self.settings.output_dir).save_file() {
+                            self.settings.output_dir = path.parent().map(Path::to_path_buf);
+                            self.output_prim_path = path.display().to_string();
+                        }
+                    }
+                    ui.add_space(210.0);
+                    ui.label("Game Version:");
+                    version_combo(
+                        ui,
+                        "Output Game Version",
+                        &mut self.settings.output_version,
+                    );
+                });
+                ui.label("Output PRIM file:");
+                ui.add_sized(
+                    [640.0, 0.0],
+                    egui::TextEdit::singleline(&mut self.output_prim_path),
+                );
+
+                ui.add_space(10.0);
 
-            ui.vertical_centered(|ui| {
-                let button = ui.add_sized([100.0, 40.0], egui::Button::new("Port!"));
-                if button.clicked() {
-                    self.prim_port.input_prim_path = PathBuf::from(&self.input_prim_path);
-                    self.prim_port.output_prim_path = PathBuf::from(&self.output_prim_path);
-                    self.prim_port.input_version = GameVersion::try_from(self.input_version as u8).unwrap();
-                    self.prim_port.output_version = GameVersion::try_from(self.output_version as u8).unwrap();
-                    self.prim_port.port();
-                    self.window_open = true;
+                if ui.button("Select Conversion Profile…").clicked() {
+                    if let Some(path) = rfd::FileDialog::new()
+                        .add_filter("TOML", &["toml"])
+                        .pick_file()
+                    {
+                        self.settings.profile_path = path.display().to_string();
+                    }
                 }
+                ui.label("Conversion profile (Leave empty to use the built-in profile):");
+                ui.add_sized(
+                    [640.0, 0.0],
+                    egui::TextEdit::singleline(&mut self.settings.profile_path),
+                );
+
+                ui.add_space(10.0);
+
+                egui::ScrollArea::vertical()
+                    .max_height(220.0)
+                    .show(ui, |ui| self.settings.options.ui(ui));
+
+                ui.separator();
+
+                ui.vertical_centered(|ui| {
+                    let button = ui.add_sized([100.0, 40.0], egui::Button::new("Port!"));
+                    if button.clicked() {
+                        self.start("Port", true, |mut prim_port| {
+                            prim_port.port()?;
+                            Ok(Output::Ported)
+                        });
+                    }
+                    ui.add_space(4.0);
+                    if ui.button("Preview").clicked() {
+                        self.start("Preview", false, |prim_port| {
+                            let (input, ported) = prim_port.preview()?;
+                            Ok(Output::Preview(
+                                input.preview_meshes(),
+                                ported.preview_meshes(),
+                            ))
+                        });
+                    }
+                    ui.add_space(4.0);
+                    if ui.button("Inspect").clicked() {
+                        self.start("Inspect", false, |prim_port| {
+                            Ok(Output::Inspect(prim_port.inspect()?))
+                        });
+                    }
+                    ui.add_space(4.0);
+                    if ui.button("Hex View").clicked() {
+                        self.start("Hex View", false, |prim_port| {
+                            let (data, coverage) = prim_port.hexmap()?;
+                            Ok(Output::HexView(data, coverage))
+                        });
+                    }
+                    if let Some(task) = self.worker.task() {
+                        ui.add_space(4.0);
+                        ui.horizontal(|ui| {
+                            ui.spinner();
+                            ui.label(format!("{}…", task));
+                        });
+                    }
+                });
             });
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.set_enabled(enabled);
+            ui.horizontal(|ui| {
+                ui.selectable_value(&mut self.tab, Tab::Preview, "Preview");
+                ui.selectable_value(&mut self.tab, Tab::Inspector, "Inspector");
+                ui.selectable_value(&mut self.tab, Tab::HexView, "Hex View");
+                ui.selectable_value(&mut self.tab, Tab::Queue, "Batch Queue");
+            });
+            ui.separator();
+            match self.tab {
+                Tab::Preview => self.preview.ui(ui),
+                Tab::Inspector => self.inspector_ui(ui),
+                Tab::HexView => self.hex_view.ui(ui),
+                Tab::Queue => {
+                    if let Some(jobs) = self.queue.ui(ui, self.worker.is_running()) {
+                        self.run_batch(jobs);
+                    }
+                }
+            }
         });
     }
 }
+
+impl MyApp {
+    /// Runs a job with the current options on the worker thread, after checking the paths it
+    /// needs are set.
+    fn start(
+        &mut self,
+        task: &'static str,
+        needs_output: bool,
+        run: impl FnOnce(PrimPort) -> log::Result<Output> + Send + 'static,
+    ) {
+        if !self.update_prim_port(task) {
+            return;
+        }
+        let missing = if self.input_prim_path.is_empty() {
+            Some("Select an input PRIM file")
+        } else if needs_output && self.output_prim_path.is_empty() {
+            Some("Select an output PRIM file")
+        } else {
+            None
+        };
+        if let Some(message) = missing {
+            self.failure = Some(Failure::new(task, message));
+            return;
+        }
+        let prim_port = self.prim_port.clone();
+        if prim_port.rpkg_path.is_none() {
+            self.add_recent_file(&prim_port.input_prim_path);
+        }
+        self.worker.start(task, move || run(prim_port));
+    }
+
+    fn finish(&mut self, output: Output) {
+        match output {
+            Output::Ported => {
+                self.window_message = "PRIM Successfully Ported!";
+                self.window_open = true;
+            }
+            Output::Batch => {
+                self.window_message = "Batch Finished!";
+                self.window_open = true;
+            }
+            Output::Saved => {
+                self.inspected_changed = false;
+                self.window_message = "PRIM Successfully Saved!";
+                self.window_open = true;
+            }
+            Output::Preview(input, ported) => {
+                self.preview.set(input, ported);
+                self.tab = Tab::Preview;
+            }
+            Output::Inspect(prim) => {
+                self.inspected = Some(Arc::new(Mutex::new(prim)));
+                self.inspected_changed = false;
+                self.tab = Tab::Inspector;
+            }
+            Output::HexView(data, coverage) => {
+                self.hex_view.set(data, coverage);
+                self.tab = Tab::HexView;
+            }
+        }
+    }
+
+    /// Ports queued files one after another on the worker thread, with the options of the Port
+    /// panel, reporting the status of each file to the queue.
+    fn run_batch(&mut self, jobs: Vec<Job>) {
+        if !self.update_prim_port("Batch") {
+            return;
+        }
+        let base = self.prim_port.clone();
+        let template = self.queue.template.clone();
+        let updates = self.queue.listen();
+        self.worker.start("Batch", move || {
+            for job in jobs {
+                let _ = updates.send((job.index, Status::Running));
+                let status = match batch::prim_port(
+                    &base,
+                    &job.input,
+                    job.input_version,
+                    job.output_version,
+                    &template,
+                )
+                .and_then(batch::port)
+                {
+                    Ok(()) => Status::Done,
+                    Err(message) => Status::Failed(message),
+                };
+                let _ = updates.send((job.index, status));
+            }
+            Ok(Output::Batch)
+        });
+    }
+
+    fn inspector_ui(&mut self, ui: &mut egui::Ui) {
+        let Some(inspected) = self.inspected.clone() else {
+            ui.label("Click Inspect to load the input PRIM");
+            return;
+        };
+        let mut save = false;
+        ui.horizontal(|ui| {
+            save = ui
+                .add_enabled(
+                    self.inspected_changed && !self.worker.is_running(),
+                    egui::Button::new("Save to Output PRIM file"),
+                )
+                .clicked();
+            if self.inspected_changed {
+                ui.label("Unsaved changes");
+            }
+        });
+        if save && self.update_prim_port("Save") {
+            let prim = inspected.clone();
+            if self.output_prim_path.is_empty() {
+                self.failure = Some(Failure::new("Save", "Select an output PRIM file"));
+            } else {
+                let prim_port = self.prim_port.clone();
+                self.worker.start("Save", move || {
+                    prim_port.save(&lock(&prim))?;
+                    Ok(Output::Saved)
+                });
+            }
+        }
+        if self.worker.task() == Some("Save") {
+            return;
+        }
+        let mut prim = lock(&inspected);
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            self.inspected_changed |= prim.inspect(ui);
+        });
+    }
+
+    /// Moves an input PRIM file to the front of the recent files.
+    fn add_recent_file(&mut self, path: &Path) {
+        let recent_files = &mut self.settings.recent_files;
+        recent_files.retain(|recent| recent != path);
+        recent_files.insert(0, path.to_path_buf());
+        recent_files.truncate(MAX_RECENT_FILES);
+    }
+
+    /// Copies the GUI fields into the port, reporting invalid options as a failure of the task.
+    fn update_prim_port(&mut self, task: &'static str) -> bool {
+        self.prim_port.input_prim_path = PathBuf::from(&self.input_prim_path);
+        self.prim_port.output_prim_path = PathBuf::from(&self.output_prim_path);
+        self.prim_port.input_version = self.settings.input_version;
+        self.prim_port.output_version = self.settings.output_version;
+        self.prim_port.profile_path = if self.settings.profile_path.is_empty() {
+            None
+        } else {
+            Some(PathBuf::from(&self.settings.profile_path))
+        };
+        if let Err(message) = self.settings.options.apply(&mut self.prim_port) {
+            self.failure = Some(Failure::new(task, &message));
+            return false;
+        }
+        true
+    }
+}
+
+/// A file dialog starting in the directory last used for the same kind of file.
+fn dialog(directory: &Option<PathBuf>) -> rfd::FileDialog {
+    match directory {
+        Some(directory) => rfd::FileDialog::new().set_directory(directory),
+        None => rfd::FileDialog::new(),
+    }
+}
+
+/// Locks the inspected PRIM, which a failed save leaves poisoned but intact.
+fn lock(prim: &Mutex<Prim>) -> MutexGuard<'_, Prim> {
+    prim.lock().unwrap_or_else(|err| err.into_inner())
+}
@@ -0,0 +1,171 @@
+use crate::prim::{Block, Coverage, Range};
+use eframe::egui;
+use egui::{Color32, RichText};
+
+/// Bytes per row of the hex view.
+const ROW_BYTES: usize = 16;
+
+const BLOCKS: [Block; 15] = [
+    Block::File,
+    Block::Header,
+    Block::ObjectTable,
+    Block::Object,
+    Block::SubMeshTable,
+    Block::SubMesh,
+    Block::Vertices,
+    Block::Indices,
+    Block::Collision,
+    Block::Cloth,
+    Block::CopyBones,
+    Block::BoneIndices,
+    Block::BoneInfo,
+    Block::Padding,
+    Block::Gap,
+];
+
+/// Hex dump of the input PRIM with every byte colored by the structure parsed from it.
+#[derive(Default)]
+pub struct HexView {
+    data: Vec<u8>,
+    layout: Vec<Range>,
+    issues: Vec<String>,
+    shared: Vec<String>,
+    /// Index into the layout of every byte.
+    ranges: Vec<usize>,
+    selected: Option<usize>,
+    scroll_to: Option<usize>,
+}
+
+impl HexView {
+    pub fn set(&mut self, data: Vec<u8>, coverage: Coverage) {
+        let issues = coverage.issues();
+        let shared = coverage.shared_structures();
+        let layout = coverage.layout;
+        let mut ranges = vec![0; data.len()];
+        for (index, range) in layout.iter().enumerate() {
+            ranges[range.start as usize..range.end as usize].fill(index);
+        }
+        *self = HexView {
+            data,
+            layout,
+            issues,
+            shared,
+            ranges,
+            selected: None,
+            scroll_to: None,
+        };
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        if self.data.is_empty() {
+            ui.label("Click Hex View to map the bytes of the input PRIM");
+            return;
+        }
+        ui.horizontal_wrapped(|ui| {
+            for block in BLOCKS {
+                if self.layout.iter().any(|range| range.block == block) {
+                    ui.label(RichText::new(block.name()).color(color(block)));
+                }
+            }
+        });
+        if !self.issues.is_empty() {
+            egui::CollapsingHeader::new(format!("{} issues", self.issues.len())).show(ui, |ui| {
+                for issue in &self.issues {
+                    ui.colored_label(color(Block::Gap), issue);
+                }
+            });
+        }
+        if !self.shared.is_empty() {
+            let header = format!("{} shared structures", self.shared.len());
+            egui::CollapsingHeader::new(header).show(ui, |ui| {
+                for shared in &self.shared {
+                    ui.label(shared);
+                }
+            });
+        }
+        ui.separator();
+        ui.horizontal_top(|ui| {
+            ui.vertical(|ui| {
+                ui.set_width(300.0);
+                egui::ScrollArea::vertical()
+                    .id_source("Ranges")
+                    .auto_shrink([false, false])
+                    .show(ui, |ui| self.ranges_ui(ui));
+            });
+            ui.separator();
+            ui.vertical(|ui| self.hex_ui(ui));
+        });
+    }
+
+    fn ranges_ui(&mut self, ui: &mut egui::Ui) {
+        for (index, range) in self.layout.iter().enumerate() {
+            let text = RichText::new(format!("{:#08x} {}", range.start, range.label()))
+                .monospace()
+                .color(color(range.block));
+            let response = ui
+                .selectable_label(self.selected == Some(index), text)
+                .on_hover_text(format!("{} bytes", range.end - range.start));
+            if response.clicked() {
+                self.selected = Some(index);
+                self.scroll_to = Some(range.start as usize / ROW_BYTES);
+            }
+        }
+    }
+
+    fn hex_ui(&mut self, ui: &mut egui::Ui) {
+        let row_height = ui.text_style_height(&egui::TextStyle::Monospace) + 2.0;
+        let num_rows = self.data.len().div_ceil(ROW_BYTES);
+        let mut scroll_area = egui::ScrollArea::vertical()
+            .id_source("Hex")
+            .auto_shrink([false, false]);
+        if let Some(row) = self.scroll_to.take() {
+            scroll_area = scroll_area.vertical_scroll_offset(row as f32 * row_height);
+        }
+        let mut clicked = None;
+        scroll_area.show_rows(ui, row_height, num_rows, |ui, rows| {
+            ui.spacing_mut().item_spacing = egui::vec2(4.0, 2.0);
+            for row in rows {
+                let start = row * ROW_BYTES;
+                let end = (start + ROW_BYTES).min(self.data.len());
+                ui.horizontal(|ui| {
+                    ui.monospace(format!("{:06x}", start));
+                    for offset in start..end {
+                        let index = self.ranges[offset];
+                        let range = &self.layout[index];
+                        let mut text = RichText::new(format!("{:02x}", self.data[offset]))
+                            .monospace()
+                            .color(color(range.block));
+                        if self.selected == Some(index) {
+                            text = text.background_color(Color32::from_gray(64));
+                        }
+                        let response = ui
+                            .add(egui::Label::new(text).sense(egui::Sense::click()))
+                            .on_hover_text(format!("{:#x}: {}", offset, range.label()));
+                        if response.clicked() {
+                            clicked = Some(index);
+                        }
+                    }
+                    let text: String = self.data[start..end]
+                        .iter()
+                        .map(|&byte| match byte {
+                            0x20..=0x7E => byte as char,
+                            _ => '.',
+                        })
+                        .collect();
+                    for _ in end..start + ROW_BYTES {
+                        ui.monospace("  ");
+                    }
+                    ui.monospace(text);
+                });
+            }
+        });
+        if clicked.is_some() {
+            self.selected = clicked;
+        }
+    }
+}
+
+fn color(block: Block) -> Color32 {
+    let [r, g, b] = block.color();
+    Color32::from_rgb(r, g, b)
+}
@@ -0,0 +1,270 @@
+use crate::prim::{ColorOperation, Packing, Transform};
+use crate::PrimPort;
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+const PACKINGS: [(Option<Packing>, &str); 4] = [
+    (None, "Profile"),
+    (Some(Packing::Auto), "Auto"),
+    (Some(Packing::Separate), "Separate"),
+    (Some(Packing::Interleaved), "Interleaved"),
+];
+
+const MIRROR_AXES: [(Option<usize>, &str); 4] = [
+    (None, "None"),
+    (Some(0), "X"),
+    (Some(1), "Y"),
+    (Some(2), "Z"),
+];
+
+/// The port options of the CLI. Transforms, vertex color edits and where the output goes in a
+/// patch archive apply to one PRIM, so they are not persisted with the others.
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct Options {
+    no_cloth: bool,
+    verbose: bool,
+    packing: Option<Packing>,
+    rpkg_path: String,
+    #[serde(skip)]
+    patch_rpkg_path: String,
+    #[serde(skip)]
+    resource: String,
+    material_map_path: String,
+    #[serde(skip)]
+    scale: [f32; 3],
+    #[serde(skip)]
+    rotate: [f32; 3],
+    #[serde(skip)]
+    mirror: Option<usize>,
+    #[serde(skip)]
+    translate: [f32; 3],
+    #[serde(skip)]
+    matrix: String,
+    #[serde(skip)]
+    set_color: Option<[u8; 4]>,
+    #[serde(skip)]
+    color1_to_colors: bool,
+    #[serde(skip)]
+    bake_ao: bool,
+    #[serde(skip)]
+    ao_samples: u32,
+    #[serde(skip)]
+    colors_to_color1: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            no_cloth: false,
+            verbose: false,
+            packing: None,
+            rpkg_path: String::new(),
+            patch_rpkg_path: String::new(),
+            resource: String::new(),
+            material_map_path: String::new(),
+            scale: [1.0; 3],
+            rotate: [0.0; 3],
+            mirror: None,
+            translate: [0.0; 3],
+            matrix: String::new(),
+            set_color: None,
+            color1_to_colors: false,
+            bake_ao: false,
+            ao_samples: 32,
+            colors_to_color1: false,
+        }
+    }
+}
+
+impl Options {
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new("Options").show(ui, |ui| {
+            ui.checkbox(&mut self.no_cloth, "Remove cloth meshes")
+                .on_hover_text("When porting from ALPHA, overrides the profile");
+            ui.checkbox(&mut self.verbose, "Verbose debug output");
+            ui.horizontal(|ui| {
+                ui.label("Vertex packing:");
+                egui::ComboBox::from_id_source("Vertex Packing")
+                    .selected_text(packing_name(self.packing))
+                    .show_ui(ui, |ui| {
+                        for (packing, name) in PACKINGS {
+                            ui.selectable_value(&mut self.packing, packing, name);
+                        }
+                    });
+            });
+            path_field(
+                ui,
+                "RPKG archive to read the input PRIM from (The input is a resource hash or path):",
+                &mut self.rpkg_path,
+                false,
+            );
+            path_field(
+                ui,
+                "Patch RPKG archive to add the output PRIM to (The output is a resource hash or path):",
+                &mut self.patch_rpkg_path,
+                true,
+            );
+            ui.label("Resource hash or path of the output PRIM (Leave empty to keep the input resource):");
+            ui.add_sized([620.0, 0.0], egui::TextEdit::singleline(&mut self.resource));
+            path_field(
+                ui,
+                "Material map of FROM=TO material ids or MATI hashes:",
+                &mut self.material_map_path,
+                false,
+            );
+        });
+        egui::CollapsingHeader::new("Transform").show(ui, |ui| {
+            egui::Grid::new("Transform").show(ui, |ui| {
+                vector_row(ui, "Scale", &mut self.scale, 0.01);
+                vector_row(ui, "Rotate", &mut self.rotate, 1.0);
+                vector_row(ui, "Translate", &mut self.translate, 0.01);
+                ui.label("Mirror");
+                egui::ComboBox::from_id_source("Mirror Axis")
+                    .selected_text(MIRROR_AXES[self.mirror.map_or(0, |axis| axis + 1)].1)
+                    .show_ui(ui, |ui| {
+                        for (axis, name) in MIRROR_AXES {
+                            ui.selectable_value(&mut self.mirror, axis, name);
+                        }
+                    });
+                ui.end_row();
+            });
+            ui.label("4x4 row-major matrix of 16 comma separated values, applied last:");
+            ui.add_sized([620.0, 0.0], egui::TextEdit::singleline(&mut self.matrix));
+        });
+        egui::CollapsingHeader::new("Vertex colors").show(ui, |ui| {
+            ui.horizontal(|ui| {
+                let mut set = self.set_color.is_some();
+                ui.checkbox(&mut set, "Set all vertex colors and color1");
+                match (set, &mut self.set_color) {
+                    (true, Some(color)) => {
+                        ui.color_edit_button_srgba_unmultiplied(color);
+                    }
+                    (true, None) => self.set_color = Some([255; 4]),
+                    (false, _) => self.set_color = None,
+                }
+            });
+            ui.checkbox(
+                &mut self.color1_to_colors,
+                "Expand color1 into per-vertex colors",
+            );
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut self.bake_ao, "Bake ambient occlusion into the alpha");
+                ui.add_enabled(
+                    self.bake_ao,
+                    egui::DragValue::new(&mut self.ao_samples)
+                        .clamp_range(1..=1024)
+                        .suffix(" rays"),
+                );
+            });
+            ui.checkbox(
+                &mut self.colors_to_color1,
+                "Replace uniform per-vertex colors with color1",
+            );
+        });
+    }
+
+    /// Copies the options into the port, or returns why they are invalid.
+    pub fn apply(&self, prim_port: &mut PrimPort) -> Result<(), String> {
+        let path = |path: &String| (!path.is_empty()).then(|| PathBuf::from(path));
+        prim_port.no_cloth = self.no_cloth;
+        prim_port.verbose = self.verbose;
+        prim_port.packing = self.packing;
+        prim_port.rpkg_path = path(&self.rpkg_path);
+        prim_port.patch_rpkg_path = path(&self.patch_rpkg_path);
+        prim_port.resource = (!self.resource.is_empty()).then(|| self.resource.clone());
+        prim_port.material_map_path = path(&self.material_map_path);
+        prim_port.transform = self.transform()?;
+        prim_port.color_operations = self.color_operations();
+        Ok(())
+    }
+
+    fn transform(&self) -> Result<Option<Transform>, String> {
+        let mut transform = Transform::identity();
+        let mut is_identity = true;
+        if self.scale != [1.0; 3] {
+            transform = transform.scale(self.scale);
+            is_identity = false;
+        }
+        if self.rotate != [0.0; 3] {
+            transform = transform.rotate(self.rotate);
+            is_identity = false;
+        }
+        if let Some(axis) = self.mirror {
+            transform = transform.mirror(axis);
+            is_identity = false;
+        }
+        if self.translate != [0.0; 3] {
+            transform = transform.translate(self.translate);
+            is_identity = false;
+        }
+        if !self.matrix.trim().is_empty() {
+            let values = self
+                .matrix
+                .split(',')
+                .map(|value| value.trim().parse::<f32>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|err| format!("Matrix value is not a number: {}", err))?;
+            let rows = values
+                .try_into()
+                .map_err(|_| String::from("Matrix takes 16 values"))?;
+            transform = transform.then(Transform::from_rows(rows));
+            is_identity = false;
+        }
+        Ok((!is_identity).then_some(transform))
+    }
+
+    fn color_operations(&self) -> Vec<ColorOperation> {
+        let mut operations = Vec::new();
+        if let Some(color) = self.set_color {
+            operations.push(ColorOperation::Set(color));
+        }
+        if self.color1_to_colors {
+            operations.push(ColorOperation::Color1ToColors);
+        }
+        if self.bake_ao {
+            operations.push(ColorOperation::BakeAmbientOcclusion(self.ao_samples.max(1)));
+        }
+        if self.colors_to_color1 {
+            operations.push(ColorOperation::ColorsToColor1);
+        }
+        operations
+    }
+}
+
+fn packing_name(packing: Option<Packing>) -> &'static str {
+    PACKINGS
+        .iter()
+        .find(|(value, _)| *value == packing)
+        .unwrap()
+        .1
+}
+
+fn path_field(ui: &mut egui::Ui, label: &str, path: &mut String, save: bool) {
+    ui.horizontal(|ui| {
+        ui.label(label);
+        if ui.small_button("…").clicked() {
+            let dialog = rfd::FileDialog::new();
+            let picked = if save {
+                dialog.save_file()
+            } else {
+                dialog.pick_file()
+            };
+            if let Some(picked) = picked {
+                *path = picked.display().to_string();
+            }
+        }
+    });
+    ui.add_sized([620.0, 0.0], egui::TextEdit::singleline(path));
+}
+
+fn vector_row(ui: &mut egui::Ui, label: &str, vector: &mut [f32; 3], speed: f64) {
+    ui.label(label);
+    ui.horizontal(|ui| {
+        for value in vector {
+            ui.add(egui::DragValue::new(value).speed(speed));
+        }
+    });
+    ui.end_row();
+}
@@ -0,0 +1,380 @@
+use crate::prim::PreviewMesh;
+use eframe::egui;
+use egui::epaint::{Mesh, Vertex, WHITE_UV};
+use egui::{Color32, ColorImage, PointerButton, Pos2, Rect, Sense, Shape, Stroke, TextureHandle};
+use std::collections::HashSet;
+
+#[derive(Clone, Copy, PartialEq)]
+enum RenderMode {
+    Shaded,
+    Wireframe,
+    Normals,
+    UvChecker,
+    VertexColors,
+}
+
+const RENDER_MODES: [(RenderMode, &str); 5] = [
+    (RenderMode::Shaded, "Shaded"),
+    (RenderMode::Wireframe, "Wireframe"),
+    (RenderMode::Normals, "Normals"),
+    (RenderMode::UvChecker, "UV Checker"),
+    (RenderMode::VertexColors, "Vertex Colors"),
+];
+
+/// Field of view of the preview camera, in radians.
+const FOV: f32 = 0.8;
+
+/// Orbit camera around a target point, with Z up like the game.
+struct Camera {
+    target: [f32; 3],
+    yaw: f32,
+    pitch: f32,
+    distance: f32,
+}
+
+/// Camera basis and projection for one frame.
+struct View {
+    eye: [f32; 3],
+    right: [f32; 3],
+    up: [f32; 3],
+    forward: [f32; 3],
+    center: Pos2,
+    focal: f32,
+}
+
+impl Camera {
+    fn fit(meshes: &[PreviewMesh]) -> Camera {
+        let mut min = [f32::MAX; 3];
+        let mut max = [f32::MIN; 3];
+        for position in meshes.iter().flat_map(|mesh| &mesh.positions) {
+            for axis in 0..3 {
+                min[axis] = min[axis].min(position[axis]);
+                max[axis] = max[axis].max(position[axis]);
+            }
+        }
+        if min[0] > max[0] {
+            return Camera::default();
+        }
+        let radius = length(sub(max, min)).max(0.01) / 2.0;
+        Camera {
+            target: [0, 1, 2].map(|axis| (min[axis] + max[axis]) / 2.0),
+            distance: radius / (FOV / 2.0).tan() * 1.2,
+            ..Camera::default()
+        }
+    }
+
+    fn view(&self, rect: Rect) -> View {
+        let (sin_yaw, cos_yaw) = self.yaw.sin_cos();
+        let (sin_pitch, cos_pitch) = self.pitch.sin_cos();
+        let back = [cos_pitch * cos_yaw, cos_pitch * sin_yaw, sin_pitch];
+        let forward = back.map(|x| -x);
+        let right = normalize(cross(forward, [0.0, 0.0, 1.0]));
+        View {
+            eye: [0, 1, 2].map(|axis| self.target[axis] + back[axis] * self.distance),
+            right,
+            up: cross(right, forward),
+            forward,
+            center: rect.center(),
+            focal: rect.height() / 2.0 / (FOV / 2.0).tan(),
+        }
+    }
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Camera {
+            target: [0.0; 3],
+            yaw: 0.8,
+            pitch: 0.4,
+            distance: 5.0,
+        }
+    }
+}
+
+impl View {
+    /// Screen position and depth of a point, or None behind the near plane.
+    fn project(&self, position: [f32; 3]) -> Option<(Pos2, f32)> {
+        let relative = sub(position, self.eye);
+        let depth = dot(relative, self.forward);
+        if depth < 0.001 {
+            return None;
+        }
+        let x = dot(relative, self.right) / depth * self.focal;
+        let y = dot(relative, self.up) / depth * self.focal;
+        Some((Pos2::new(self.center.x + x, self.center.y - y), depth))
+    }
+}
+
+/// 3D viewport comparing the input PRIM with the PRIM the port would write.
+pub struct Preview {
+    input: Vec<PreviewMesh>,
+    ported: Vec<PreviewMesh>,
+    show_ported: bool,
+    hidden: HashSet<String>,
+    mode: RenderMode,
+    camera: Camera,
+    checker: Option<TextureHandle>,
+    /// Shapes drawn into a viewport rect, kept until the camera, the mode or the shown objects
+    /// change so the triangles are not sorted every frame.
+    shapes: Option<(Rect, Vec<Shape>)>,
+}
+
+impl Default for Preview {
+    fn default() -> Self {
+        Preview {
+            input: Vec::new(),
+            ported: Vec::new(),
+            show_ported: false,
+            hidden: HashSet::new(),
+            mode: RenderMode::Shaded,
+            camera: Camera::default(),
+            checker: None,
+            shapes: None,
+        }
+    }
+}
+
+impl Preview {
+    pub fn set(&mut self, input: Vec<PreviewMesh>, ported: Vec<PreviewMesh>) {
+        self.camera = Camera::fit(&input);
+        self.input = input;
+        self.ported = ported;
+        self.hidden.clear();
+        self.shapes = None;
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        let Preview {
+            input,
+            ported,
+            show_ported,
+            hidden,
+            mode,
+            camera,
+            checker,
+            shapes,
+        } = self;
+        let mut changed = false;
+        ui.horizontal(|ui| {
+            changed |= ui.selectable_value(show_ported, false, "Input").changed();
+            changed |= ui.selectable_value(show_ported, true, "Ported").changed();
+            ui.separator();
+            for (value, name) in RENDER_MODES {
+                changed |= ui.selectable_value(mode, value, name).changed();
+            }
+        });
+        let meshes: &[PreviewMesh] = if *show_ported { ported } else { input };
+        ui.collapsing("Objects", |ui| {
+            for mesh in meshes {
+                let mut visible = !hidden.contains(&mesh.name);
+                if ui.checkbox(&mut visible, &mesh.name).changed() {
+                    if visible {
+                        hidden.remove(&mesh.name);
+                    } else {
+                        hidden.insert(mesh.name.clone());
+                    }
+                    changed = true;
+                }
+            }
+        });
+
+        let (response, painter) = ui.allocate_painter(ui.available_size(), Sense::drag());
+        let rect = response.rect;
+        painter.rect_filled(rect, 0.0, Color32::from_gray(24));
+        if meshes.is_empty() {
+            painter.text(
+                rect.center(),
+                egui::Align2::CENTER_CENTER,
+                "Click Preview to load the input PRIM",
+                egui::FontId::proportional(16.0),
+                Color32::GRAY,
+            );
+            return;
+        }
+
+        let delta = response.drag_delta();
+        if response.dragged_by(PointerButton::Primary) {
+            camera.yaw -= delta.x * 0.01;
+            camera.pitch = (camera.pitch + delta.y * 0.01).clamp(-1.5, 1.5);
+            changed |= delta != egui::Vec2::ZERO;
+        } else if response.dragged_by(PointerButton::Secondary)
+            || response.dragged_by(PointerButton::Middle)
+        {
+            let view = camera.view(rect);
+            let scale = camera.distance / view.focal;
+            for axis in 0..3 {
+                camera.target[axis] -=
+                    (view.right[axis] * delta.x - view.up[axis] * delta.y) * scale;
+            }
+            changed |= delta != egui::Vec2::ZERO;
+        }
+        if response.hovered() {
+            let scroll = ui.input(|input| input.scroll_delta.y);
+            camera.distance *= (-scroll * 0.002).exp();
+            changed |= scroll != 0.0;
+        }
+
+        if changed || shapes.as_ref().is_none_or(|(drawn, _)| *drawn != rect) {
+            let texture_id = if *mode == RenderMode::UvChecker {
+                checker_texture(checker, ui.ctx()).id()
+            } else {
+                egui::TextureId::default()
+            };
+            let view = camera.view(rect);
+            let meshes = meshes.iter().filter(|mesh| !hidden.contains(&mesh.name));
+            let drawn = if *mode == RenderMode::Wireframe {
+                wireframe(meshes, &view)
+            } else {
+                vec![Shape::mesh(triangles(meshes, &view, *mode, texture_id))]
+            };
+            *shapes = Some((rect, drawn));
+        }
+        if let Some((_, shapes)) = shapes {
+            painter.with_clip_rect(rect).extend(shapes.iter().cloned());
+        }
+    }
+}
+
+fn wireframe<'a>(meshes: impl Iterator<Item = &'a PreviewMesh>, view: &View) -> Vec<Shape> {
+    let stroke = Stroke::new(1.0, Color32::from_rgb(160, 200, 255));
+    let mut shapes = Vec::new();
+    for mesh in meshes {
+        let projected: Vec<_> = mesh.positions.iter().map(|p| view.project(*p)).collect();
+        for triangle in mesh.indices.chunks_exact(3) {
+            for (a, b) in [(0, 1), (1, 2), (2, 0)] {
+                let a = projected[triangle[a] as usize];
+                let b = projected[triangle[b] as usize];
+                if let (Some((a, _)), Some((b, _))) = (a, b) {
+                    shapes.push(Shape::line_segment([a, b], stroke));
+                }
+            }
+        }
+    }
+    shapes
+}
+
+/// The triangles of the meshes as one egui mesh, sorted back to front.
+fn triangles<'a>(
+    meshes: impl Iterator<Item = &'a PreviewMesh>,
+    view: &View,
+    mode: RenderMode,
+    texture_id: egui::TextureId,
+) -> Mesh {
+    let mut triangles = Vec::new();
+    for mesh in meshes {
+        let projected: Vec<_> = mesh.positions.iter().map(|p| view.project(*p)).collect();
+        for triangle in mesh.indices.chunks_exact(3) {
+            let indices = [0, 1, 2].map(|i| triangle[i] as usize);
+            let Some(points) = indices
+                .iter()
+                .map(|i| projected[*i])
+                .collect::<Option<Vec<_>>>()
+            else {
+                continue;
+            };
+            let depth = points.iter().map(|(_, depth)| depth).sum::<f32>();
+            let positions = indices.map(|i| mesh.positions[i]);
+            let face_normal = normalize(cross(
+                sub(positions[1], positions[0]),
+                sub(positions[2], positions[0]),
+            ));
+            let uv_offset = indices
+                .iter()
+                .filter_map(|i| mesh.uvs.get(*i))
+                .fold([f32::MAX; 2], |min, uv| {
+                    [min[0].min(uv[0]), min[1].min(uv[1])]
+                })
+                .map(f32::floor);
+            let vertices = [0, 1, 2].map(|corner| {
+                let i = indices[corner];
+                let normal = mesh.normals.get(i).copied().unwrap_or(face_normal);
+                let (color, uv) = match mode {
+                    RenderMode::Shaded | RenderMode::Wireframe => {
+                        let light = dot(face_normal, view.forward).abs();
+                        (Color32::from_gray((60.0 + 180.0 * light) as u8), WHITE_UV)
+                    }
+                    RenderMode::Normals => {
+                        let [r, g, b] = normal.map(|x| ((x * 0.5 + 0.5) * 255.0) as u8);
+                        (Color32::from_rgb(r, g, b), WHITE_UV)
+                    }
+                    RenderMode::UvChecker => {
+                        let uv = mesh.uvs.get(i).copied().unwrap_or_default();
+                        let uv = Pos2::new(uv[0] - uv_offset[0], uv[1] - uv_offset[1]);
+                        (Color32::WHITE, uv)
+                    }
+                    RenderMode::VertexColors => {
+                        let [r, g, b, _] = mesh.colors[i];
+                        (Color32::from_rgb(r, g, b), WHITE_UV)
+                    }
+                };
+                Vertex {
+                    pos: points[corner].0,
+                    uv,
+                    color,
+                }
+            });
+            triangles.push((depth, vertices));
+        }
+    }
+    // Painter's algorithm: egui meshes have no depth buffer, so draw back to front.
+    triangles.sort_by(|a, b| b.0.total_cmp(&a.0));
+    let mut mesh = Mesh::with_texture(texture_id);
+    for (_, vertices) in triangles {
+        let index = mesh.vertices.len() as u32;
+        mesh.vertices.extend(vertices);
+        mesh.indices.extend([index, index + 1, index + 2]);
+    }
+    mesh
+}
+
+fn checker_texture<'a>(
+    checker: &'a mut Option<TextureHandle>,
+    ctx: &egui::Context,
+) -> &'a TextureHandle {
+    checker.get_or_insert_with(|| {
+        let size = 8;
+        let pixels = (0..size * size)
+            .map(|i| {
+                if (i % size + i / size) % 2 == 0 {
+                    Color32::from_gray(230)
+                } else {
+                    Color32::from_rgb(200, 60, 60)
+                }
+            })
+            .collect();
+        let image = ColorImage {
+            size: [size, size],
+            pixels,
+        };
+        ctx.load_texture("uv_checker", image, egui::TextureOptions::NEAREST)
+    })
+}
+
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn length(a: [f32; 3]) -> f32 {
+    dot(a, a).sqrt()
+}
+
+fn normalize(a: [f32; 3]) -> [f32; 3] {
+    let length = length(a);
+    if length > 0.0 {
+        a.map(|x| x / length)
+    } else {
+        a
+    }
+}
@@ -0,0 +1,281 @@
+use crate::{batch, GameVersion};
+use eframe::egui;
+use egui::Color32;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{Receiver, Sender};
+
+const VERSIONS: [GameVersion; 4] = [
+    GameVersion::Hma,
+    GameVersion::Alpha,
+    GameVersion::Hm2016,
+    GameVersion::Woa,
+];
+
+#[derive(Clone)]
+pub enum Status {
+    Pending,
+    Running,
+    Done,
+    Failed(String),
+}
+
+/// A queued PRIM file. Without an input version it is detected when the file is ported.
+struct Row {
+    input: PathBuf,
+    input_version: Option<GameVersion>,
+    output_version: GameVersion,
+    /// Output path of the last run, from the template at the time
+    output: Option<PathBuf>,
+    status: Status,
+}
+
+/// A queued file to port, sent to the worker thread.
+pub struct Job {
+    pub index: usize,
+    pub input: PathBuf,
+    pub input_version: Option<GameVersion>,
+    pub output_version: GameVersion,
+}
+
+/// Files dropped onto the window or added with the file dialogs, ported in the background.
+pub struct Queue {
+    rows: Vec<Row>,
+    pub template: String,
+    output_version: GameVersion,
+    updates: Option<Receiver<(usize, Status)>>,
+}
+
+impl Default for Queue {
+    fn default() -> Self {
+        Self {
+            rows: Vec::new(),
+            template: batch::DEFAULT_TEMPLATE.to_string(),
+            output_version: GameVersion::Woa,
+            updates: None,
+        }
+    }
+}
+
+impl Queue {
+    /// Adds the PRIM files, and the PRIM files in folders, that are not queued yet.
+    pub fn add(&mut self, paths: &[PathBuf]) {
+        for input in batch::collect_inputs(paths) {
+            if self.rows.iter().any(|row| row.input == input) {
+                continue;
+            }
+            self.rows.push(Row {
+                input,
+                input_version: None,
+                output_version: self.output_version,
+                output: None,
+                status: Status::Pending,
+            });
+        }
+    }
+
+    /// Status updates of the running batch are sent to the queue through `sender`.
+    pub fn listen(&mut self) -> Sender<(usize, Status)> {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        self.updates = Some(receiver);
+        sender
+    }
+
+    pub fn poll(&mut self) {
+        let Some(updates) = &self.updates else {
+            return;
+        };
+        for (index, status) in updates.try_iter() {
+            if let Some(row) = self.rows.get_mut(index) {
+                row.status = status;
+            }
+        }
+    }
+
+    /// Returns the jobs to run when Run or a Retry button is clicked.
+    pub fn ui(&mut self, ui: &mut egui::Ui, running: bool) -> Option<Vec<Job>> {
+        let mut run = None;
+        ui.add_enabled_ui(!running, |ui| {
+            ui.horizontal(|ui| {
+                if ui.button("Add files…").clicked() {
+                    if let Some(paths) = rfd::FileDialog::new()
+                        .add_filter("PRIM", &["prim"])
+                        .pick_files()
+                    {
+                        self.add(&paths);
+                    }
+                }
+                if ui.button("Add folder…").clicked() {
+                    if let Some(path) = rfd::FileDialog::new().pick_folder() {
+                        self.add(&[path]);
+                    }
+                }
+                if ui.button("Clear").clicked() {
+                    self.rows.clear();
+                }
+                ui.separator();
+                ui.label("Output version for new files:");
+                version_combo(ui, "Queue Output Version", &mut self.output_version);
+            });
+            ui.horizontal(|ui| {
+                ui.label("Output path:");
+                ui.add_sized(
+                    [ui.available_width() - 80.0, 0.0],
+                    egui::TextEdit::singleline(&mut self.template),
+                )
+                .on_hover_text("{dir}, {name}, {stem} and {version} are replaced for each file");
+                let pending = self
+                    .rows
+                    .iter()
+                    .any(|row| !matches!(row.status, Status::Done));
+                if ui.add_enabled(pending, egui::Button::new("Run")).clicked() {
+                    run = Some(self.jobs(|_, row| !matches!(row.status, Status::Done)));
+                }
+            });
+        });
+        ui.separator();
+        if self.rows.is_empty() {
+            ui.label("Drop PRIM files or folders onto the window to queue them");
+            return run;
+        }
+        let mut retry = None;
+        let mut remove = None;
+        egui::ScrollArea::vertical()
+            .auto_shrink([false, false])
+            .show(ui, |ui| {
+                egui::Grid::new("Queue")
+                    .num_columns(5)
+                    .striped(true)
+                    .show(ui, |ui| {
+                        ui.strong("Input PRIM file");
+                        ui.strong("Input");
+                        ui.strong("Output");
+                        ui.strong("Status");
+                        ui.end_row();
+                        for (index, row) in self.rows.iter_mut().enumerate() {
+                            let name = row.input.file_name().unwrap().to_str().unwrap();
+                            ui.label(name)
+                                .on_hover_text(row.input.display().to_string());
+                            ui.add_enabled_ui(!running, |ui| {
+                                egui::ComboBox::from_id_source(("Queue Input Version", index))
+                                    .selected_text(match row.input_version {
+                                        Some(version) => version_name(version),
+                                        None => String::from("Auto"),
+                                    })
+                                    .show_ui(ui, |ui| {
+                                        ui.selectable_value(&mut row.input_version, None, "Auto");
+                                        for version in VERSIONS {
+                                            ui.selectable_value(
+                                                &mut row.input_version,
+                                                Some(version),
+                                                version_name(version),
+                                            );
+                                        }
+                                    });
+                            });
+                            ui.add_enabled_ui(!running, |ui| {
+                                version_combo(
+                                    ui,
+                                    ("Queue Output Version", index),
+                                    &mut row.output_version,
+                                );
+                            });
+                            match &row.status {
+                                Status::Pending => ui.label("Pending"),
+                                Status::Running => {
+                                    ui.horizontal(|ui| {
+                                        ui.spinner();
+                                        ui.label("Running");
+                                    })
+                                    .response
+                                }
+                                Status::Done => ui.colored_label(Color32::GREEN, "Done"),
+                                Status::Failed(message) => ui
+                                    .colored_label(Color32::RED, "Failed")
+                                    .on_hover_text(message),
+                            };
+                            ui.add_enabled_ui(!running, |ui| {
+                                ui.horizontal(|ui| {
+                                    if let Status::Failed(_) = row.status {
+                                        if ui.small_button("Retry").clicked() {
+                                            retry = Some(index);
+                                        }
+                                    }
+                                    if let (Status::Done, Some(output)) = (&row.status, &row.output)
+                                    {
+                                        if ui.small_button("Open folder").clicked() {
+                                            open_folder(output);
+                                        }
+                                    }
+                                    if ui.small_button("Remove").clicked() {
+                                        remove = Some(index);
+                                    }
+                                });
+                            });
+                            ui.end_row();
+                        }
+                    });
+            });
+        if let Some(index) = retry {
+            run = Some(self.jobs(|row_index, _| row_index == index));
+        }
+        if let Some(index) = remove {
+            self.rows.remove(index);
+        }
+        run
+    }
+
+    /// Jobs for the selected rows, which are marked as pending.
+    fn jobs(&mut self, selected: impl Fn(usize, &Row) -> bool) -> Vec<Job> {
+        let mut jobs = Vec::new();
+        for (index, row) in self.rows.iter_mut().enumerate() {
+            if !selected(index, row) {
+                continue;
+            }
+            row.status = Status::Pending;
+            row.output = Some(batch::output_path(
+                &self.template,
+                &row.input,
+                &row.output_version,
+            ));
+            jobs.push(Job {
+                index,
+                input: row.input.clone(),
+                input_version: row.input_version,
+                output_version: row.output_version,
+            });
+        }
+        jobs
+    }
+}
+
+fn version_name(version: GameVersion) -> String {
+    version.try_into().unwrap()
+}
+
+pub fn version_combo(ui: &mut egui::Ui, id: impl std::hash::Hash, version: &mut GameVersion) {
+    egui::ComboBox::from_id_source(id)
+        .selected_text(version_name(*version))
+        .show_ui(ui, |ui| {
+            for value in VERSIONS {
+                ui.selectable_value(version, value, version_name(value));
+            }
+        });
+}
+
+/// Opens the folder of an output file in the file manager of the system.
+fn open_folder(output: &Path) {
+    let path = match output.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let program = if cfg!(target_os = "windows") {
+        "explorer"
+    } else if cfg!(target_os = "macos") {
+        "open"
+    } else {
+        "xdg-open"
+    };
+    if let Err(err) = std::process::Command::new(program).arg(path).spawn() {
+        log!("Warning: Cannot open folder {}: {}", path.display(), err);
+    }
+}
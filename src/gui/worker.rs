@@ -0,0 +1,112 @@
+use crate::log;
+use crate::prim::{Coverage, PreviewMesh, Prim};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver};
+use std::thread::{self, JoinHandle};
+
+/// Result of a job run on the worker thread.
+pub enum Output {
+    Ported,
+    Saved,
+    Batch,
+    Preview(Vec<PreviewMesh>, Vec<PreviewMesh>),
+    Inspect(Prim),
+    HexView(Vec<u8>, Coverage),
+}
+
+/// A failed job: what it was doing, the error, and the file and offset it was found at when
+/// known.
+pub struct Failure {
+    pub task: &'static str,
+    pub message: String,
+    pub file: Option<PathBuf>,
+    pub offset: Option<u64>,
+}
+
+impl Failure {
+    /// A job that was not started, because of invalid options.
+    pub fn new(task: &'static str, message: &str) -> Failure {
+        Failure {
+            task,
+            message: message.to_string(),
+            file: None,
+            offset: None,
+        }
+    }
+}
+
+struct Job {
+    task: &'static str,
+    handle: JoinHandle<log::Result<Output>>,
+}
+
+/// Runs the porting code off the UI thread, collecting everything it prints.
+#[derive(Default)]
+pub struct Worker {
+    log: Vec<String>,
+    receiver: Option<Receiver<String>>,
+    job: Option<Job>,
+}
+
+impl Worker {
+    pub fn start(
+        &mut self,
+        task: &'static str,
+        run: impl FnOnce() -> log::Result<Output> + Send + 'static,
+    ) {
+        let (sender, receiver) = mpsc::channel();
+        self.receiver = Some(receiver);
+        self.job = Some(Job {
+            task,
+            handle: thread::spawn(move || {
+                log::capture(sender);
+                run()
+            }),
+        });
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.job.is_some()
+    }
+
+    /// The task of the running job.
+    pub fn task(&self) -> Option<&'static str> {
+        self.job.as_ref().map(|job| job.task)
+    }
+
+    pub fn log(&self) -> &[String] {
+        &self.log
+    }
+
+    pub fn clear_log(&mut self) {
+        self.log.clear();
+    }
+
+    /// Collects new output, and the result once the job has finished.
+    pub fn poll(&mut self) -> Option<Result<Output, Failure>> {
+        if let Some(receiver) = &self.receiver {
+            self.log.extend(receiver.try_iter());
+        }
+        if !self.job.as_ref()?.handle.is_finished() {
+            return None;
+        }
+        let job = self.job.take().unwrap();
+        let result = job.handle.join();
+        if let Some(receiver) = self.receiver.take() {
+            self.log.extend(receiver.try_iter());
+        }
+        // Errors are returned, a panic is a bug in the porting code and only has its message.
+        Some(match result {
+            Ok(result) => result.map_err(|err| Failure {
+                task: job.task,
+                message: err.message,
+                file: err.file,
+                offset: err.offset,
+            }),
+            Err(payload) => Err(Failure::new(
+                job.task,
+                &log::panic_message(payload.as_ref()),
+            )),
+        })
+    }
+}
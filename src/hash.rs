@@ -0,0 +1,50 @@
+use md5::{Digest, Md5};
+
+/// Parses a resource hash in hex, or hashes a resource path such as [assembly:/...].pc_prim.
+pub fn parse_resource_id(resource: &str) -> u64 {
+    let hex = resource.trim_start_matches("0x");
+    if !hex.is_empty() && hex.len() <= 16 && hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        u64::from_str_radix(hex, 16).unwrap()
+    } else {
+        resource_hash(resource)
+    }
+}
+
+/// IOI resource hash: the first 8 bytes of the MD5 of the lowercase path, top byte cleared.
+pub fn resource_hash(path: &str) -> u64 {
+    let digest = Md5::digest(path.to_lowercase().as_bytes());
+    u64::from_be_bytes(digest[..8].try_into().unwrap()) & 0x00FFFFFFFFFFFFFF
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resource_hash_matches_md5() {
+        // MD5("") is d41d8cd98f00b204e9800998ecf8427e.
+        assert_eq!(resource_hash(""), 0x001D8CD98F00B204);
+        assert_eq!(
+            resource_hash("[assembly:/_pro/props/crate.prim].pc_prim"),
+            0x00E5E0289F5804EB
+        );
+    }
+
+    #[test]
+    fn resource_hash_ignores_case() {
+        assert_eq!(
+            resource_hash("[ASSEMBLY:/_PRO/props/crate.prim].pc_prim"),
+            0x00E5E0289F5804EB
+        );
+    }
+
+    #[test]
+    fn parses_hex_or_hashes_paths() {
+        assert_eq!(parse_resource_id("00E5E0289F5804EB"), 0x00E5E0289F5804EB);
+        assert_eq!(parse_resource_id("0x1234"), 0x1234);
+        assert_eq!(
+            parse_resource_id("[assembly:/_pro/props/crate.prim].pc_prim"),
+            0x00E5E0289F5804EB
+        );
+    }
+}
@@ -58,25 +58,25 @@ pub fn read_u32_array(reader: &mut Cursor<Vec<u8>>, size: usize) -> Vec<u32> {
 }
 
 pub fn write_f32_into(writer: &mut Cursor<Vec<u8>>, data: &[f32]) {
-    for i in 0..data.len() {
-        writer.write_f32::<LittleEndian>(data[i]).unwrap();
+    for value in data {
+        writer.write_f32::<LittleEndian>(*value).unwrap();
     }
 }
 
 pub fn write_u8_into(writer: &mut Cursor<Vec<u8>>, data: &[u8]) {
-    for i in 0..data.len() {
-        writer.write_u8(data[i]).unwrap();
+    for value in data {
+        writer.write_u8(*value).unwrap();
     }
 }
 
 pub fn write_u16_into(writer: &mut Cursor<Vec<u8>>, data: &[u16]) {
-    for i in 0..data.len() {
-        writer.write_u16::<LittleEndian>(data[i]).unwrap();
+    for value in data {
+        writer.write_u16::<LittleEndian>(*value).unwrap();
     }
 }
 
 pub fn write_u32_into(writer: &mut Cursor<Vec<u8>>, data: &[u32]) {
-    for i in 0..data.len() {
-        writer.write_u32::<LittleEndian>(data[i]).unwrap();
+    for value in data {
+        writer.write_u32::<LittleEndian>(*value).unwrap();
     }
 }
@@ -0,0 +1,79 @@
+use std::any::Any;
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+
+/// Prints a line like `println!`, and sends it to the sink of this thread when its output is
+/// captured, so the GUI can show the output of the porting code that runs on its worker thread.
+macro_rules! log {
+    () => {
+        $crate::log::write(String::new())
+    };
+    ($($arg:tt)*) => {
+        $crate::log::write(format!($($arg)*))
+    };
+}
+
+thread_local! {
+    static SINK: RefCell<Option<Sender<String>>> = const { RefCell::new(None) };
+}
+
+/// An error that stops reading, porting or writing a PRIM: the message printed when it was
+/// raised, and the file and offset it was found at when known.
+#[derive(Debug)]
+pub struct Error {
+    pub message: String,
+    pub file: Option<PathBuf>,
+    pub offset: Option<u64>,
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /// Attributes the error to `file`, unless it already names one.
+    pub fn in_file(mut self, file: &Path) -> Error {
+        self.file.get_or_insert_with(|| file.to_path_buf());
+        self
+    }
+
+    /// Attributes the error to an offset of the file being read.
+    pub fn at(mut self, offset: u64) -> Error {
+        self.offset = Some(offset);
+        self
+    }
+}
+
+/// Prints an error and returns it, for the caller to pass up.
+pub fn error(message: String) -> Error {
+    write(message.clone());
+    Error {
+        message,
+        file: None,
+        offset: None,
+    }
+}
+
+/// Sends the lines printed on this thread to `sender` as well.
+pub fn capture(sender: Sender<String>) {
+    SINK.with(|sink| *sink.borrow_mut() = Some(sender));
+}
+
+pub fn write(line: String) {
+    println!("{}", line);
+    SINK.with(|sink| {
+        if let Some(sender) = sink.borrow().as_ref() {
+            let _ = sender.send(line);
+        }
+    });
+}
+
+/// The message of a panic payload.
+pub fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        String::from("Unknown error")
+    }
+}
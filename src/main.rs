@@ -1,30 +1,382 @@
+#[macro_use]
+mod log;
+mod batch;
 mod gui;
+mod hash;
 mod io;
 mod prim;
-use clap::Parser;
+mod profile;
+mod rpkg;
+use clap::{Args, Parser, Subcommand};
 use eframe::egui;
-use prim::Prim;
-use std::path::PathBuf;
+use prim::{ColorOperation, Coverage, MaterialMap, Packing, Prim, Selection, Transform};
+use profile::Profile;
+use rpkg::{Entry, MetaFormat, Rpkg};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 
 #[derive(Parser, Default)]
+#[command(args_conflicts_with_subcommands = true)]
 struct Cli {
-    /// Remove cloth meshes (When porting from ALPHA)
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// Remove cloth meshes (When porting from ALPHA, overrides the profile)
     #[arg(short = 'c')]
     no_cloth: bool,
     /// Enable verbose debug output
     #[arg(short = 'v')]
     verbose: bool,
-    /// Path to input PRIM file to port
+    /// Vertex packing of the output PRIM: AUTO, SEPARATE, INTERLEAVED (Overrides the profile)
+    #[arg(short = 'p')]
+    packing: Option<String>,
+    /// Path to a TOML conversion profile (Defaults to the built-in profile of the version pair)
+    #[arg(long)]
+    profile: Option<PathBuf>,
+    /// Path to an RPKG archive to read the input PRIM from
+    #[arg(long, global = true)]
+    rpkg: Option<PathBuf>,
+    /// Path to a patch RPKG archive to add the output PRIM to, created if it does not exist
+    #[arg(long)]
+    patch_rpkg: Option<PathBuf>,
+    /// Resource hash or path of the output PRIM, written to its meta file (Defaults to the input resource)
+    #[arg(long)]
+    resource: Option<String>,
+    /// Path to a material map of FROM=TO material ids or MATI hashes, applied to every object
+    #[arg(long)]
+    material_map: Option<PathBuf>,
+    /// Path to input PRIM file to port (Resource hash or path with --rpkg)
     input_prim: Option<PathBuf>,
     /// Input PRIM game version: HMA, ALPHA, HM2016, WOA
     input_version: Option<String>,
     /// Output PRIM game version: HMA, ALPHA, HM2016, WOA
     output_version: Option<String>,
-    /// Path to output ported PRIM file
+    /// Path to output ported PRIM file (Resource hash or path with --patch-rpkg)
     output_prim: Option<PathBuf>,
+    #[command(flatten)]
+    transform: TransformArgs,
+    #[command(flatten)]
+    colors: ColorArgs,
 }
 
-#[derive(Default, Clone, Copy)]
+#[derive(Args, Default)]
+#[command(next_help_heading = "Transform")]
+struct TransformArgs {
+    /// Scale meshes by S or by X,Y,Z
+    #[arg(long, value_delimiter = ',', allow_hyphen_values = true)]
+    scale: Vec<f32>,
+    /// Rotate meshes by X,Y,Z degrees
+    #[arg(long, value_delimiter = ',', allow_hyphen_values = true)]
+    rotate: Vec<f32>,
+    /// Mirror meshes along an axis: X, Y, Z
+    #[arg(long)]
+    mirror: Option<String>,
+    /// Translate meshes by X,Y,Z
+    #[arg(long, value_delimiter = ',', allow_hyphen_values = true)]
+    translate: Vec<f32>,
+    /// Apply a 4x4 row-major matrix of 16 comma separated values, after the other transforms
+    #[arg(long, value_delimiter = ',', allow_hyphen_values = true)]
+    matrix: Vec<f32>,
+}
+
+impl TransformArgs {
+    fn transform(&self) -> Option<Transform> {
+        let mut transform = Transform::identity();
+        let mut is_identity = true;
+        if !self.scale.is_empty() {
+            transform = match self.scale.as_slice() {
+                [s] => transform.scale([*s; 3]),
+                [x, y, z] => transform.scale([*x, *y, *z]),
+                _ => {
+                    log!("Error: --scale takes either S or X,Y,Z");
+                    std::process::exit(1);
+                }
+            };
+            is_identity = false;
+        }
+        if !self.rotate.is_empty() {
+            transform = transform.rotate(transform_values(&self.rotate, "--rotate", "X,Y,Z"));
+            is_identity = false;
+        }
+        if let Some(axis) = &self.mirror {
+            let axis = match axis.to_lowercase().as_str() {
+                "x" => 0,
+                "y" => 1,
+                "z" => 2,
+                _ => {
+                    log!(
+                        "Error: Mirror axis is unknown: {}\nEnter one of the following: X, Y, Z",
+                        axis
+                    );
+                    std::process::exit(1);
+                }
+            };
+            transform = transform.mirror(axis);
+            is_identity = false;
+        }
+        if !self.translate.is_empty() {
+            transform =
+                transform.translate(transform_values(&self.translate, "--translate", "X,Y,Z"));
+            is_identity = false;
+        }
+        if !self.matrix.is_empty() {
+            transform = transform.then(Transform::from_rows(transform_values(
+                &self.matrix,
+                "--matrix",
+                "16 values",
+            )));
+            is_identity = false;
+        }
+        if is_identity {
+            None
+        } else {
+            Some(transform)
+        }
+    }
+}
+
+fn transform_values<const N: usize>(values: &[f32], option: &str, expected: &str) -> [f32; N] {
+    match values.try_into() {
+        Ok(values) => values,
+        Err(_) => {
+            log!("Error: {} takes {}", option, expected);
+            std::process::exit(1);
+        }
+    }
+}
+
+#[derive(Args, Default)]
+#[command(next_help_heading = "Vertex colors")]
+struct ColorArgs {
+    /// Set all vertex colors and color1 to an RRGGBBAA hex color
+    #[arg(long, value_parser = parse_color)]
+    set_color: Option<[u8; 4]>,
+    /// Expand color1 into per-vertex colors
+    #[arg(long)]
+    color1_to_colors: bool,
+    /// Bake ambient occlusion into the vertex color alpha
+    #[arg(long)]
+    bake_ao: bool,
+    /// Number of rays per vertex used to bake ambient occlusion
+    #[arg(long, default_value_t = 32)]
+    ao_samples: u32,
+    /// Replace uniform per-vertex colors with color1
+    #[arg(long)]
+    colors_to_color1: bool,
+}
+
+impl ColorArgs {
+    fn operations(&self) -> Vec<ColorOperation> {
+        let mut operations = Vec::new();
+        if let Some(color) = self.set_color {
+            operations.push(ColorOperation::Set(color));
+        }
+        if self.color1_to_colors {
+            operations.push(ColorOperation::Color1ToColors);
+        }
+        if self.bake_ao {
+            operations.push(ColorOperation::BakeAmbientOcclusion(self.ao_samples.max(1)));
+        }
+        if self.colors_to_color1 {
+            operations.push(ColorOperation::ColorsToColor1);
+        }
+        operations
+    }
+}
+
+fn parse_color(value: &str) -> Result<[u8; 4], String> {
+    let value = value.trim_start_matches('#');
+    if value.len() != 8 {
+        return Err(String::from("Color must be 8 hex digits: RRGGBBAA"));
+    }
+    let color = u32::from_str_radix(value, 16).map_err(|err| err.to_string())?;
+    Ok(color.to_be_bytes())
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Merge the objects of multiple PRIM files into one PRIM file
+    Merge {
+        /// Paths to input PRIM files to merge
+        #[arg(required = true, num_args = 2..)]
+        input_prims: Vec<PathBuf>,
+        /// Input PRIM game version: HMA, ALPHA, HM2016, WOA
+        #[arg(short = 'i')]
+        input_version: String,
+        /// Output PRIM game version: HMA, ALPHA, HM2016, WOA (Defaults to the input version)
+        #[arg(short = 't')]
+        output_version: Option<String>,
+        /// Path to output merged PRIM file
+        #[arg(short = 'o')]
+        output_prim: PathBuf,
+        /// Bone index remapping as FROM=TO, for weighted inputs with a different bone rig
+        #[arg(short = 'b', value_parser = parse_bone_map)]
+        bone_map: Vec<(u8, u8)>,
+    },
+    /// Print a summary of the header and objects of a PRIM file
+    Info {
+        /// Path to input PRIM file (Resource hash or path with --rpkg)
+        input_prim: PathBuf,
+        /// Input PRIM game version: HMA, ALPHA, HM2016, WOA
+        #[arg(short = 'i')]
+        input_version: String,
+    },
+    /// Export the meshes of a PRIM file to a Wavefront OBJ file
+    Export {
+        /// Path to input PRIM file (Resource hash or path with --rpkg)
+        input_prim: PathBuf,
+        /// Input PRIM game version: HMA, ALPHA, HM2016, WOA
+        #[arg(short = 'i')]
+        input_version: String,
+        /// Path to output OBJ file
+        #[arg(short = 'o')]
+        output_obj: PathBuf,
+    },
+    /// Extract the selected objects into a new PRIM file
+    Extract {
+        #[command(flatten)]
+        edit: EditArgs,
+        #[command(flatten)]
+        selection: SelectionArgs,
+    },
+    /// Remove the selected objects from a PRIM file
+    Remove {
+        #[command(flatten)]
+        edit: EditArgs,
+        #[command(flatten)]
+        selection: SelectionArgs,
+    },
+    /// Compare two PRIM files field by field
+    Diff {
+        /// Path to the first PRIM file
+        prim_a: PathBuf,
+        /// Path to the second PRIM file
+        prim_b: PathBuf,
+        /// Game version of the first PRIM file: HMA, ALPHA, HM2016, WOA
+        #[arg(short = 'a')]
+        version_a: String,
+        /// Game version of the second PRIM file: HMA, ALPHA, HM2016, WOA (Defaults to the first version)
+        #[arg(short = 'b')]
+        version_b: Option<String>,
+        /// Largest difference between vertex stream values that is not reported
+        #[arg(long, default_value_t = 1e-4)]
+        tolerance: f32,
+    },
+    /// Print the byte ranges of a PRIM file, labeled with the structure parsed from each
+    Hexmap {
+        /// Path to input PRIM file (Resource hash or path with --rpkg)
+        input_prim: PathBuf,
+        /// Input PRIM game version: HMA, ALPHA, HM2016, WOA
+        #[arg(short = 'i')]
+        input_version: String,
+        /// Rows of 16 bytes printed per range, 0 to print the labels only
+        #[arg(long, default_value_t = 4)]
+        rows: usize,
+        /// Color the ranges with ANSI escape codes
+        #[arg(long)]
+        color: bool,
+    },
+    /// Port many PRIM files, or the PRIM files in folders, in one go
+    Batch {
+        /// Paths to input PRIM files or folders of PRIM files
+        #[arg(required = true)]
+        inputs: Vec<PathBuf>,
+        /// Input PRIM game version: HMA, ALPHA, HM2016, WOA (Detected per file by default)
+        #[arg(short = 'i')]
+        input_version: Option<String>,
+        /// Output PRIM game version: HMA, ALPHA, HM2016, WOA
+        #[arg(short = 't')]
+        output_version: String,
+        /// Output path template, using {dir}, {name}, {stem} and {version} of each input
+        #[arg(short = 'o', default_value = batch::DEFAULT_TEMPLATE)]
+        template: String,
+        /// Remove cloth meshes (When porting from ALPHA, overrides the profile)
+        #[arg(short = 'c')]
+        no_cloth: bool,
+        /// Path to a TOML conversion profile (Defaults to the built-in profile of the version pair)
+        #[arg(long)]
+        profile: Option<PathBuf>,
+    },
+    /// Print the IOI resource hash of resource paths
+    Hash {
+        /// Resource paths, such as [assembly:/_pro/characters/assets/.../example.prim].pc_prim
+        #[arg(required = true)]
+        paths: Vec<String>,
+    },
+    /// Print the built-in conversion profile of a version pair as TOML
+    Profile {
+        /// Input PRIM game version: HMA, ALPHA, HM2016, WOA
+        input_version: String,
+        /// Output PRIM game version: HMA, ALPHA, HM2016, WOA
+        output_version: String,
+    },
+}
+
+#[derive(Args)]
+struct EditArgs {
+    /// Path to input PRIM file
+    input_prim: PathBuf,
+    /// Input PRIM game version: HMA, ALPHA, HM2016, WOA
+    #[arg(short = 'i')]
+    input_version: String,
+    /// Output PRIM game version: HMA, ALPHA, HM2016, WOA (Defaults to the input version)
+    #[arg(short = 't')]
+    output_version: Option<String>,
+    /// Path to output PRIM file
+    #[arg(short = 'o')]
+    output_prim: PathBuf,
+}
+
+#[derive(Args)]
+struct SelectionArgs {
+    /// Select objects by index
+    #[arg(long = "index")]
+    indices: Vec<usize>,
+    /// Select objects by material ID
+    #[arg(long = "material-id")]
+    material_ids: Vec<u16>,
+    /// Select objects sharing any bit with this LOD mask, e.g. 0x01
+    #[arg(long = "lod-mask", value_parser = parse_mask)]
+    lod_mask: Option<u8>,
+    /// Select objects by variant ID
+    #[arg(long = "variant-id")]
+    variant_ids: Vec<u8>,
+}
+
+impl SelectionArgs {
+    fn selection(self) -> Selection {
+        let selection = Selection {
+            indices: self.indices,
+            material_ids: self.material_ids,
+            lod_mask: self.lod_mask,
+            variant_ids: self.variant_ids,
+        };
+        if selection.is_empty() {
+            log!("Error: No objects selected, use --index, --material-id, --lod-mask or --variant-id");
+            std::process::exit(1);
+        }
+        selection
+    }
+}
+
+fn parse_mask(value: &str) -> Result<u8, String> {
+    match value.strip_prefix("0x") {
+        Some(hex) => u8::from_str_radix(hex, 16),
+        None => value.parse::<u8>(),
+    }
+    .map_err(|err| err.to_string())
+}
+
+fn parse_bone_map(value: &str) -> Result<(u8, u8), String> {
+    let (from, to) = value
+        .split_once('=')
+        .ok_or(format!("Bone map entry must be FROM=TO: {}", value))?;
+    let from = from.trim().parse::<u8>().map_err(|err| err.to_string())?;
+    let to = to.trim().parse::<u8>().map_err(|err| err.to_string())?;
+    Ok((from, to))
+}
+
+#[derive(Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum GameVersion {
     #[default]
     Hma,
@@ -54,7 +406,6 @@ impl TryInto<String> for GameVersion {
             Self::Alpha => Ok("ALPHA".to_string()),
             Self::Hm2016 => Ok("HM2016".to_string()),
             Self::Woa => Ok("WOA".to_string()),
-            _ => Err(String::from("Error")),
         }
     }
 }
@@ -64,14 +415,14 @@ fn game_version_check(version: &String) -> GameVersion {
         "hma" =>
         //GameVersion::Hma,
         {
-            println!("Error: HMA is not supported yet.");
+            log!("Error: HMA is not supported yet.");
             std::process::exit(1)
         }
         "alpha" => GameVersion::Alpha,
         "hm2016" => GameVersion::Hm2016,
         "woa" => GameVersion::Woa,
         _ => {
-            println!(
+            log!(
                 "Error: Input PRIM game version is unknown: {}\nEnter one of the following: HMA, ALPHA, HM2016, WOA",
                 version,
             );
@@ -81,7 +432,22 @@ fn game_version_check(version: &String) -> GameVersion {
     input_version
 }
 
-#[derive(Default)]
+fn packing_check(packing: &String) -> Packing {
+    match packing.to_lowercase().as_str() {
+        "auto" => Packing::Auto,
+        "separate" => Packing::Separate,
+        "interleaved" => Packing::Interleaved,
+        _ => {
+            log!(
+                "Error: Vertex packing is unknown: {}\nEnter one of the following: AUTO, SEPARATE, INTERLEAVED",
+                packing,
+            );
+            std::process::exit(1);
+        }
+    }
+}
+
+#[derive(Default, Clone)]
 struct PrimPort {
     input_prim_path: PathBuf,
     output_prim_path: PathBuf,
@@ -90,15 +456,42 @@ struct PrimPort {
     no_cloth: bool,
     verbose: bool,
     use_gui: bool,
+    packing: Option<Packing>,
+    profile_path: Option<PathBuf>,
+    rpkg_path: Option<PathBuf>,
+    patch_rpkg_path: Option<PathBuf>,
+    resource: Option<String>,
+    material_map_path: Option<PathBuf>,
+    transform: Option<Transform>,
+    color_operations: Vec<ColorOperation>,
 }
 
 impl PrimPort {
     fn from(args: Cli) -> PrimPort {
-        if args.input_prim.is_none()
-            || args.input_version.is_none()
-            || args.output_version.is_none()
-            || args.output_prim.is_none()
-        {
+        if let (Some(input_prim), Some(input_version), Some(output_version), Some(output_prim)) = (
+            &args.input_prim,
+            &args.input_version,
+            &args.output_version,
+            &args.output_prim,
+        ) {
+            PrimPort {
+                input_prim_path: input_prim.clone(),
+                output_prim_path: output_prim.clone(),
+                input_version: game_version_check(input_version),
+                output_version: game_version_check(output_version),
+                no_cloth: args.no_cloth,
+                verbose: args.verbose,
+                use_gui: false,
+                packing: args.packing.as_ref().map(packing_check),
+                profile_path: args.profile,
+                rpkg_path: args.rpkg,
+                patch_rpkg_path: args.patch_rpkg,
+                resource: args.resource,
+                material_map_path: args.material_map,
+                transform: args.transform.transform(),
+                color_operations: args.colors.operations(),
+            }
+        } else {
             PrimPort {
                 input_prim_path: PathBuf::new(),
                 output_prim_path: PathBuf::new(),
@@ -107,48 +500,470 @@ impl PrimPort {
                 no_cloth: false,
                 verbose: false,
                 use_gui: true,
-            }
-        } else {
-            PrimPort {
-                input_prim_path: args.input_prim.clone().unwrap_or_else(|| PathBuf::new()),
-                output_prim_path: args.output_prim.clone().unwrap_or_else(|| PathBuf::new()),
-                input_version: game_version_check(&args.input_version.unwrap()),
-                output_version: game_version_check(&args.output_version.unwrap()),
-                no_cloth: args.no_cloth,
-                verbose: args.verbose,
-                use_gui: false,
+                packing: None,
+                profile_path: None,
+                rpkg_path: None,
+                patch_rpkg_path: None,
+                resource: None,
+                material_map_path: None,
+                transform: None,
+                color_operations: Vec::new(),
             }
         }
     }
 
-    fn port(&mut self) {
-        println!(
+    fn port(&mut self) -> log::Result<()> {
+        log!(
             "Porting input PRIM file: {}",
             &self.input_prim_path.to_str().unwrap()
         );
-        println!(
+        log!(
             "Porting from game version {} to {}",
-            TryInto::<String>::try_into(self.input_version.clone()).unwrap(),
-            TryInto::<String>::try_into(self.output_version.clone()).unwrap(),
+            TryInto::<String>::try_into(self.input_version).unwrap(),
+            TryInto::<String>::try_into(self.output_version).unwrap(),
         );
-        println!(
+        log!(
             "Porting to output PRIM file: {}",
             &self.output_prim_path.to_str().unwrap()
         );
-        let mut prim = Prim::read(&self.input_prim_path, &self.input_version, self.verbose);
-        prim.write(&self.output_prim_path, &self.output_version, self.no_cloth);
-        println!("Ported successfully!");
+        let profile = self.profile()?;
+        let (mut prim, meta) = self.read_ported(self.verbose)?;
+        match &self.patch_rpkg_path {
+            Some(path) => {
+                let data = prim.write_bytes(&self.output_version, &profile)?;
+                let references = meta.map(|(meta, _)| meta.references).unwrap_or_default();
+                self.write_patch(path, references, &data)?;
+            }
+            None => {
+                prim.write(&self.output_prim_path, &self.output_version, &profile)?;
+                let meta = match (meta, &self.resource) {
+                    (meta, None) => meta,
+                    (Some((mut meta, format)), Some(resource)) => {
+                        meta.hash = hash::parse_resource_id(resource);
+                        Some((meta, format))
+                    }
+                    (None, Some(resource)) => Some((
+                        Entry::new(hash::parse_resource_id(resource), "PRIM"),
+                        MetaFormat::Binary,
+                    )),
+                };
+                if let Some((mut meta, format)) = meta {
+                    let size = std::fs::metadata(&self.output_prim_path).unwrap().len();
+                    meta.update_data(size as u32);
+                    meta.write_meta(&self.output_prim_path, format)?;
+                }
+            }
+        }
+        log!("Ported successfully!");
+        Ok(())
+    }
+
+    /// Reads the input PRIM and applies the material map, transform and vertex color operations,
+    /// giving the PRIM to write and its resource meta with the references converted.
+    fn read_ported(&self, verbose: bool) -> log::Result<(Prim, Meta)> {
+        let (mut prim, mut meta) = read_prim(
+            &self.input_prim_path,
+            &self.input_version,
+            verbose,
+            self.rpkg_path.as_deref(),
+        )?;
+        if let Some((meta, _)) = &mut meta {
+            meta.convert_references(&self.input_version, &self.output_version);
+        }
+        if let Some(path) = &self.material_map_path {
+            log!("Applying material map: {}", path.to_str().unwrap());
+            let mut no_references = Vec::new();
+            let references = match &mut meta {
+                Some((meta, _)) => &mut meta.references,
+                None => &mut no_references,
+            };
+            prim.map_materials(&MaterialMap::load(path)?, references)?;
+        }
+        if let Some(transform) = &self.transform {
+            log!("Applying transform to meshes");
+            prim.transform(transform)?;
+        }
+        for operation in &self.color_operations {
+            prim.apply_color_operation(operation, &self.output_version);
+        }
+        Ok((prim, meta))
+    }
+
+    fn write_patch(&self, path: &Path, references: Vec<(u64, u8)>, data: &[u8]) -> log::Result<()> {
+        let hash = hash::parse_resource_id(self.output_prim_path.to_str().unwrap());
+        let version = match self.output_version {
+            GameVersion::Woa => 2,
+            GameVersion::Hma | GameVersion::Alpha | GameVersion::Hm2016 => 1,
+        };
+        if references.is_empty() {
+            log!("Warning: Input PRIM has no resource meta, the resource will not reference its bone rig or materials");
+        }
+        log!(
+            "Adding PRIM resource {:016X} to patch RPKG archive: {}",
+            hash,
+            path.to_str().unwrap()
+        );
+        let mut patch = Rpkg::open_patch(path, version)?;
+        patch.add(hash, "PRIM", references, data);
+        patch.write()
+    }
+
+    /// The input PRIM and the PRIM the port would write, with the same edits, read back without
+    /// writing it.
+    fn preview(&self) -> log::Result<(Prim, Prim)> {
+        let profile = self.profile()?;
+        let input = read_prim(
+            &self.input_prim_path,
+            &self.input_version,
+            false,
+            self.rpkg_path.as_deref(),
+        )?
+        .0;
+        let data = self
+            .read_ported(false)?
+            .0
+            .write_bytes(&self.output_version, &profile)?;
+        Ok((input, Prim::from_bytes(data, &self.output_version, false)?))
+    }
+
+    /// The input PRIM data and its layout, for the GUI hex view.
+    fn hexmap(&self) -> log::Result<(Vec<u8>, Coverage)> {
+        let (data, _) = read_prim_data(&self.input_prim_path, self.rpkg_path.as_deref())?;
+        let coverage = Prim::layout(&data, &self.input_version);
+        Ok((data, coverage))
+    }
+
+    /// The input PRIM, for editing in the GUI inspector.
+    fn inspect(&self) -> log::Result<Prim> {
+        Ok(read_prim(
+            &self.input_prim_path,
+            &self.input_version,
+            false,
+            self.rpkg_path.as_deref(),
+        )?
+        .0)
+    }
+
+    /// Writes a PRIM edited in the GUI inspector to the output PRIM file, in the input game
+    /// version it was read in. Writing converts the PRIM, so a copy is written and the inspected
+    /// PRIM stays as read.
+    fn save(&self, prim: &Prim) -> log::Result<()> {
+        log!(
+            "Saving edited PRIM to output PRIM file: {}",
+            &self.output_prim_path.to_str().unwrap()
+        );
+        let profile = Profile::builtin(&self.input_version, &self.input_version);
+        prim.clone()
+            .write(&self.output_prim_path, &self.input_version, &profile)?;
+        log!("Saved successfully!");
+        Ok(())
+    }
+
+    /// The profile file or built-in profile, with the -c and -p options applied on top.
+    fn profile(&self) -> log::Result<Profile> {
+        let mut profile = match &self.profile_path {
+            Some(path) => {
+                log!("Using conversion profile: {}", path.to_str().unwrap());
+                Profile::load(path, &self.input_version, &self.output_version)?
+            }
+            None => Profile::builtin(&self.input_version, &self.output_version),
+        };
+        if self.no_cloth {
+            profile.remove_cloth = true;
+        }
+        if let Some(packing) = self.packing {
+            profile.packing = packing;
+        }
+        Ok(profile)
+    }
+}
+
+fn merge(
+    input_prim_paths: &[PathBuf],
+    input_version: &GameVersion,
+    output_version: &GameVersion,
+    output_prim_path: &PathBuf,
+    bone_map: &HashMap<u8, u8>,
+) -> log::Result<()> {
+    log!(
+        "Merging input PRIM file: {}",
+        &input_prim_paths[0].to_str().unwrap()
+    );
+    let mut prim = Prim::read(&input_prim_paths[0], input_version, false)?;
+    for path in &input_prim_paths[1..] {
+        log!("Merging input PRIM file: {}", &path.to_str().unwrap());
+        let other = Prim::read(path, input_version, false)?;
+        prim.merge(other, path, bone_map)?;
+    }
+    log!(
+        "Writing merged PRIM file: {}",
+        &output_prim_path.to_str().unwrap()
+    );
+    prim.write(
+        output_prim_path,
+        output_version,
+        &Profile::builtin(input_version, output_version),
+    )?;
+    log!("Merged successfully!");
+    Ok(())
+}
+
+fn edit(args: EditArgs, operation: impl FnOnce(&mut Prim) -> log::Result<()>) -> log::Result<()> {
+    let input_version = game_version_check(&args.input_version);
+    let output_version = match args.output_version {
+        Some(version) => game_version_check(&version),
+        None => input_version,
+    };
+    log!(
+        "Reading input PRIM file: {}",
+        &args.input_prim.to_str().unwrap()
+    );
+    let mut prim = Prim::read(&args.input_prim, &input_version, false)?;
+    operation(&mut prim)?;
+    log!(
+        "Writing output PRIM file: {}",
+        &args.output_prim.to_str().unwrap()
+    );
+    prim.write(
+        &args.output_prim,
+        &output_version,
+        &Profile::builtin(&input_version, &output_version),
+    )
+}
+
+/// The resource meta of a PRIM and the format it was read from, if it has one.
+type Meta = Option<(Entry, MetaFormat)>;
+
+/// Reads the input PRIM from a file, or from a resource of the RPKG archive if one is given,
+/// along with the resource meta: the RPKG entry, or the meta file next to the input file.
+fn read_prim(
+    input_prim: &Path,
+    input_version: &GameVersion,
+    verbose: bool,
+    rpkg: Option<&Path>,
+) -> log::Result<(Prim, Meta)> {
+    let (data, meta) = read_prim_data(input_prim, rpkg)?;
+    let prim =
+        Prim::from_bytes(data, input_version, verbose).map_err(|err| err.in_file(input_prim))?;
+    Ok((prim, meta))
+}
+
+/// The data of the input PRIM and its resource meta, see `read_prim`.
+fn read_prim_data(input_prim: &Path, rpkg: Option<&Path>) -> log::Result<(Vec<u8>, Meta)> {
+    let Some(rpkg) = rpkg else {
+        let meta = Entry::read_meta_for(input_prim)?;
+        if meta.is_some() {
+            log!(
+                "Reading resource meta of input PRIM file: {}",
+                input_prim.to_str().unwrap()
+            );
+        }
+        let data = match std::fs::read(input_prim) {
+            Ok(data) => data,
+            Err(err) => {
+                return Err(log::error(format!(
+                    "Error opening file {}: {}",
+                    input_prim.to_str().unwrap(),
+                    err
+                ))
+                .in_file(input_prim));
+            }
+        };
+        return Ok((data, meta));
+    };
+    let archive = Rpkg::read(rpkg)?;
+    let entry = archive.find(input_prim.to_str().unwrap())?;
+    if entry.resource_type != "PRIM" {
+        return Err(log::error(format!(
+            "Error: Resource {:016X} is a {} resource, not a PRIM",
+            entry.hash, entry.resource_type
+        ))
+        .in_file(rpkg));
     }
+    log!(
+        "Reading PRIM resource {:016X} from RPKG archive: {}",
+        entry.hash,
+        rpkg.to_str().unwrap()
+    );
+    Ok((
+        archive.data(entry)?,
+        Some((entry.clone(), MetaFormat::Binary)),
+    ))
+}
+
+fn run_command(command: Command, rpkg: Option<&Path>) -> log::Result<()> {
+    // --rpkg is global so it can follow the subcommand, but only these commands read from it.
+    let reads_rpkg = matches!(
+        command,
+        Command::Info { .. } | Command::Export { .. } | Command::Hexmap { .. }
+    );
+    if rpkg.is_some() && !reads_rpkg {
+        return Err(log::error(String::from(
+            "Error: --rpkg is only supported when porting and by the info, export and hexmap commands",
+        )));
+    }
+    match command {
+        Command::Merge {
+            input_prims,
+            input_version,
+            output_version,
+            output_prim,
+            bone_map,
+        } => {
+            let input_version = game_version_check(&input_version);
+            let output_version = match output_version {
+                Some(version) => game_version_check(&version),
+                None => input_version,
+            };
+            merge(
+                &input_prims,
+                &input_version,
+                &output_version,
+                &output_prim,
+                &bone_map.into_iter().collect(),
+            )?;
+        }
+        Command::Info {
+            input_prim,
+            input_version,
+        } => {
+            let (prim, meta) = read_prim(
+                &input_prim,
+                &game_version_check(&input_version),
+                false,
+                rpkg,
+            )?;
+            let references = meta.map(|(meta, _)| meta.references).unwrap_or_default();
+            prim.print_info(&references);
+        }
+        Command::Export {
+            input_prim,
+            input_version,
+            output_obj,
+        } => {
+            let (prim, _) = read_prim(
+                &input_prim,
+                &game_version_check(&input_version),
+                false,
+                rpkg,
+            )?;
+            log!("Exporting to OBJ file: {}", &output_obj.to_str().unwrap());
+            prim.export_obj(&output_obj)?;
+            log!("Exported successfully!");
+        }
+        Command::Extract {
+            edit: args,
+            selection,
+        } => {
+            let selection = selection.selection();
+            edit(args, |prim| {
+                log!("Extracted {} objects", prim.extract(&selection)?);
+                Ok(())
+            })?;
+        }
+        Command::Remove {
+            edit: args,
+            selection,
+        } => {
+            let selection = selection.selection();
+            edit(args, |prim| {
+                log!("Removed {} objects", prim.remove(&selection)?);
+                Ok(())
+            })?;
+        }
+        Command::Diff {
+            prim_a,
+            prim_b,
+            version_a,
+            version_b,
+            tolerance,
+        } => {
+            let version_a = game_version_check(&version_a);
+            let version_b = match version_b {
+                Some(version) => game_version_check(&version),
+                None => version_a,
+            };
+            let a = Prim::read(&prim_a, &version_a, false)?;
+            let b = Prim::read(&prim_b, &version_b, false)?;
+            log!("a: {}", &prim_a.to_str().unwrap());
+            log!("b: {}", &prim_b.to_str().unwrap());
+            match a.diff(&b, tolerance) {
+                0 => log!("No differences found"),
+                differences => log!("Found {} differences", differences),
+            }
+        }
+        Command::Hexmap {
+            input_prim,
+            input_version,
+            rows,
+            color,
+        } => {
+            let (data, _) = read_prim_data(&input_prim, rpkg)?;
+            let coverage = Prim::layout(&data, &game_version_check(&input_version));
+            prim::print_hexmap(&data, &coverage, rows, color);
+        }
+        Command::Batch {
+            inputs,
+            input_version,
+            output_version,
+            template,
+            no_cloth,
+            profile,
+        } => {
+            let base = PrimPort {
+                no_cloth,
+                profile_path: profile,
+                ..Default::default()
+            };
+            batch::run(
+                &base,
+                &inputs,
+                input_version.as_ref().map(game_version_check),
+                game_version_check(&output_version),
+                &template,
+            )?;
+        }
+        Command::Hash { paths } => {
+            for path in paths {
+                log!("{:016X} {}", hash::resource_hash(&path), path);
+            }
+        }
+        Command::Profile {
+            input_version,
+            output_version,
+        } => {
+            let input_version = game_version_check(&input_version);
+            let output_version = game_version_check(&output_version);
+            log!(
+                "# Built-in conversion profile: {} -> {}",
+                TryInto::<String>::try_into(input_version).unwrap(),
+                TryInto::<String>::try_into(output_version).unwrap(),
+            );
+            print!(
+                "{}",
+                Profile::builtin(&input_version, &output_version).to_toml()
+            );
+        }
+    }
+    Ok(())
 }
 
 fn main() {
-    let args = Cli::parse();
+    let mut args = Cli::parse();
+    if let Some(command) = args.command.take() {
+        if run_command(command, args.rpkg.as_deref()).is_err() {
+            std::process::exit(1);
+        }
+        return;
+    }
     let mut prim_port = PrimPort::from(args);
     if !prim_port.use_gui {
-        prim_port.port();
+        if prim_port.port().is_err() {
+            std::process::exit(1);
+        }
     } else {
         let options = eframe::NativeOptions {
-            viewport: egui::ViewportBuilder::default().with_inner_size([640.0, 340.0]),
+            viewport: egui::ViewportBuilder::default().with_inner_size([1280.0, 640.0]),
             ..Default::default()
         };
         eframe::run_native(
@@ -1,12 +1,39 @@
 #![allow(dead_code)]
 use super::io;
+use super::log;
+use super::profile::Profile;
 use super::GameVersion;
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::{Cursor, Seek, SeekFrom};
 use std::io::{Read, Write};
-use std::path::PathBuf;
-
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+
+mod color;
+mod convert;
+mod detect;
+mod diff;
+mod export;
+mod info;
+mod inspect;
+mod layout;
+mod material;
+mod merge;
+mod preview;
+mod select;
+mod transform;
+
+pub use color::ColorOperation;
+pub use layout::{print_hexmap, Block, Coverage, Range};
+pub use material::MaterialMap;
+pub use preview::PreviewMesh;
+pub use select::Selection;
+pub use transform::Transform;
+
+#[derive(Clone)]
 struct Vertices {
     positions: Vec<[f32; 4]>,
     weights: Vec<([f32; 4], [f32; 2])>,
@@ -14,7 +41,7 @@ struct Vertices {
     normals: Vec<[f32; 4]>,
     tangents: Vec<[f32; 4]>,
     bitangents: Vec<[f32; 4]>,
-    uvs: Vec<[f32; 2]>,
+    uvs: Vec<Vec<[f32; 2]>>,
     colors: Vec<[u8; 4]>,
 }
 
@@ -42,7 +69,7 @@ impl Vertices {
         let mut normals: Vec<[f32; 4]> = Vec::new();
         let mut tangents: Vec<[f32; 4]> = Vec::new();
         let mut bitangents: Vec<[f32; 4]> = Vec::new();
-        let mut uvs = Vec::new();
+        let mut uvs = vec![Vec::new(); sub_mesh.uv_channels()];
         for _ in 0..sub_mesh.num_vertices {
             if has_alt_packing_format {
                 Vertices::read_position(&mut positions, reader, mesh, has_hi_res_positions);
@@ -50,7 +77,9 @@ impl Vertices {
             Vertices::read_vertex_data(&mut normals, reader);
             Vertices::read_vertex_data(&mut tangents, reader);
             Vertices::read_vertex_data(&mut bitangents, reader);
-            Vertices::read_uv(&mut uvs, reader, mesh);
+            for channel in &mut uvs {
+                Vertices::read_uv(channel, reader, mesh);
+            }
         }
         let mut colors = Vec::new();
         let has_color1_object = mesh.object.flags & 0x20 == 0x20;
@@ -58,8 +87,8 @@ impl Vertices {
         if is_weighted || !has_color1_object {
             if has_color1_sub_mesh {
                 let color = {
-                    if sub_mesh.object.color1.is_some() {
-                        sub_mesh.object.color1.unwrap().to_le_bytes()
+                    if let Some(color1) = &sub_mesh.object.color1 {
+                        color1.to_le_bytes()
                     } else {
                         [0; 4]
                     }
@@ -74,14 +103,14 @@ impl Vertices {
             }
         }
         Vertices {
-            positions: positions,
-            weights: weights,
-            bones: bones,
-            normals: normals,
-            tangents: tangents,
-            bitangents: bitangents,
-            uvs: uvs,
-            colors: colors,
+            positions,
+            weights,
+            bones,
+            normals,
+            tangents,
+            bitangents,
+            uvs,
+            colors,
         }
     }
 
@@ -165,15 +194,15 @@ impl Vertices {
             self.write_vertex_data(&self.normals[i], writer);
             self.write_vertex_data(&self.tangents[i], writer);
             self.write_vertex_data(&self.bitangents[i], writer);
-            self.write_uv(&self.uvs[i], writer, mesh);
+            for channel in &self.uvs {
+                self.write_uv(&channel[i], writer, mesh);
+            }
         }
         let has_color1_object = mesh.object.flags & 0x20 == 0x20;
         let has_color1_sub_mesh = sub_mesh.object.flags & 0x20 == 0x20;
-        if is_weighted || !has_color1_object {
-            if !has_color1_sub_mesh {
-                for color in &self.colors {
-                    io::write_u8_into(writer, color);
-                }
+        if (is_weighted || !has_color1_object) && !has_color1_sub_mesh {
+            for color in &self.colors {
+                io::write_u8_into(writer, color);
             }
         }
         io::align(writer, 0x10);
@@ -191,10 +220,15 @@ impl Vertices {
         } else {
             let mut compressed: [u16; 4] = [0; 4];
             compressed.iter_mut().enumerate().for_each(|(i, x)| {
-                *x = f32::round(
-                    u16::MAX as f32 * (position[i] - mesh.position_bias[i])
-                        / mesh.position_scale[i],
-                ) as u16
+                // Flat axes have a scale of 0, their positions are all the bias.
+                *x = if mesh.position_scale[i] == 0.0 {
+                    0
+                } else {
+                    f32::round(
+                        u16::MAX as f32 * (position[i] - mesh.position_bias[i])
+                            / mesh.position_scale[i],
+                    ) as u16
+                }
             });
             io::write_u16_into(writer, &compressed);
         }
@@ -237,17 +271,18 @@ impl Vertices {
     }
 
     fn print(&self) {
-        println!("vertices: {:#x?}", self.positions);
-        println!("weights: {:#x?}", self.weights);
-        println!("bones: {:#x?}", self.bones);
-        println!("normals: {:#x?}", self.normals);
-        println!("tangents: {:#x?}", self.tangents);
-        println!("bitangents: {:#x?}", self.bitangents);
-        println!("uvs: {:#x?}", self.uvs);
-        println!("colors: {:#x?}", self.colors);
+        log!("vertices: {:#x?}", self.positions);
+        log!("weights: {:#x?}", self.weights);
+        log!("bones: {:#x?}", self.bones);
+        log!("normals: {:#x?}", self.normals);
+        log!("tangents: {:#x?}", self.tangents);
+        log!("bitangents: {:#x?}", self.bitangents);
+        log!("uvs: {:#x?}", self.uvs);
+        log!("colors: {:#x?}", self.colors);
     }
 }
 
+#[derive(Clone)]
 struct Indices {
     indices: Vec<u16>,
 }
@@ -255,14 +290,14 @@ struct Indices {
 impl Indices {
     fn read(reader: &mut Cursor<Vec<u8>>, sub_mesh: &SPrimSubMesh) -> Indices {
         let count = sub_mesh.num_indices + {
-            if sub_mesh.num_indices_extra.is_some() {
-                *sub_mesh.num_indices_extra.as_ref().unwrap()
+            if let Some(num_indices_extra) = &sub_mesh.num_indices_extra {
+                *num_indices_extra
             } else {
                 0
             }
         };
         let indices = io::read_u16_array(reader, count as usize);
-        Indices { indices: indices }
+        Indices { indices }
     }
 
     fn write(&self, writer: &mut Cursor<Vec<u8>>) {
@@ -271,10 +306,11 @@ impl Indices {
     }
 
     fn print(&self) {
-        println!("indices: {:#x?}", self.indices);
+        log!("indices: {:#x?}", self.indices);
     }
 }
 
+#[derive(Clone, PartialEq)]
 struct Collision {
     bounding_boxes: Vec<[[u8; 3]; 2]>,
     triangles_per_box: u16,
@@ -292,8 +328,8 @@ impl Collision {
             ]);
         }
         Collision {
-            bounding_boxes: bounding_boxes,
-            triangles_per_box: triangles_per_box,
+            bounding_boxes,
+            triangles_per_box,
         }
     }
 
@@ -312,11 +348,12 @@ impl Collision {
     }
 
     fn print(&self) {
-        println!("bounding_boxes: {:#x?}", self.bounding_boxes);
-        println!("triangles_per_box: {:#x}", self.triangles_per_box);
+        log!("bounding_boxes: {:#x?}", self.bounding_boxes);
+        log!("triangles_per_box: {:#x}", self.triangles_per_box);
     }
 }
 
+#[derive(Clone, PartialEq)]
 struct Cloth {
     data: Vec<u8>,
 }
@@ -334,8 +371,8 @@ impl Cloth {
                 sub_mesh.num_vertices * 0x14
             }
         };
-        data.extend_from_slice(&io::read_u8_array(reader, size as usize).as_slice());
-        Cloth { data: data }
+        data.extend_from_slice(io::read_u8_array(reader, size as usize).as_slice());
+        Cloth { data }
     }
 
     fn write(&self, writer: &mut Cursor<Vec<u8>>) {
@@ -344,10 +381,11 @@ impl Cloth {
     }
 
     fn print(&self) {
-        println!("data: {:#x?}", self.data);
+        log!("data: {:#x?}", self.data);
     }
 }
 
+#[derive(Clone, PartialEq)]
 struct CopyBones {
     data: Vec<u32>,
 }
@@ -356,7 +394,7 @@ impl CopyBones {
     fn read(reader: &mut Cursor<Vec<u8>>, num_copy_bones: u32) -> CopyBones {
         let size = num_copy_bones * 2;
         let data = io::read_u32_array(reader, size as usize);
-        CopyBones { data: data }
+        CopyBones { data }
     }
 
     fn write(&self, writer: &mut Cursor<Vec<u8>>) {
@@ -365,10 +403,11 @@ impl CopyBones {
     }
 
     fn print(&self) {
-        println!("data: {:#x?}", self.data);
+        log!("data: {:#x?}", self.data);
     }
 }
 
+#[derive(Clone, PartialEq)]
 struct BoneIndices {
     data: Vec<u16>,
 }
@@ -384,7 +423,7 @@ impl BoneIndices {
             }
         };
         let data = io::read_u16_array(reader, size as usize);
-        BoneIndices { data: data }
+        BoneIndices { data }
     }
 
     fn write(&self, writer: &mut Cursor<Vec<u8>>, output_version: &GameVersion) {
@@ -401,10 +440,11 @@ impl BoneIndices {
     }
 
     fn print(&self) {
-        println!("data: {:#x?}", self.data);
+        log!("data: {:#x?}", self.data);
     }
 }
 
+#[derive(Clone, PartialEq)]
 struct BoneInfo {
     data: Vec<u8>,
 }
@@ -414,7 +454,7 @@ impl BoneInfo {
         let size = reader.read_u16::<LittleEndian>().unwrap();
         reader.seek(SeekFrom::Current(-2)).unwrap();
         let data = io::read_u8_array(reader, size as usize);
-        BoneInfo { data: data }
+        BoneInfo { data }
     }
 
     fn write(&self, writer: &mut Cursor<Vec<u8>>) {
@@ -423,7 +463,7 @@ impl BoneInfo {
     }
 
     fn print(&self) {
-        println!("data: {:#x?}", self.data);
+        log!("data: {:#x?}", self.data);
     }
 }
 
@@ -435,6 +475,7 @@ enum EPrimType {
     Shape = 5,
 }
 
+#[derive(Clone)]
 struct SPrimHeader {
     draw_destination: u8,
     pack_type: u8,
@@ -457,12 +498,25 @@ impl SPrimHeader {
     }
 
     fn print(&self) {
-        println!("draw_destination: {:#x}", self.draw_destination);
-        println!("pack_type: {:#x}", self.pack_type);
-        println!("prim_type: {:#x}", self.prim_type);
+        log!("draw_destination: {:#x}", self.draw_destination);
+        log!("pack_type: {:#x}", self.pack_type);
+        log!("prim_type: {:#x}", self.prim_type);
     }
 }
 
+/// Vertex packing of mesh objects, selected by draw_destination flag 0x80
+#[derive(Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Packing {
+    /// Keep the packing the output game version uses by default
+    #[default]
+    Auto,
+    /// Positions in a separate stream before the other vertex data
+    Separate,
+    /// Positions interleaved with the other vertex data
+    Interleaved,
+}
+
 #[repr(i32)]
 enum ObjectHeaderFlags {
     HasBones = 1,
@@ -473,11 +527,33 @@ enum ObjectHeaderFlags {
     HasHiResPositions = 0x200,
 }
 
+#[derive(Clone)]
 enum Object {
-    SPrimMesh(SPrimMesh),
-    SPrimMeshWeighted(SPrimMeshWeighted),
+    Mesh(SPrimMesh),
+    Weighted(SPrimMeshWeighted),
 }
 
+impl Object {
+    fn object(&self) -> &SPrimObject {
+        &self.mesh().object
+    }
+
+    fn mesh(&self) -> &SPrimMesh {
+        match self {
+            Object::Mesh(object) => object,
+            Object::Weighted(object) => &object.mesh,
+        }
+    }
+
+    fn mesh_mut(&mut self) -> &mut SPrimMesh {
+        match self {
+            Object::Mesh(object) => object,
+            Object::Weighted(object) => &mut object.mesh,
+        }
+    }
+}
+
+#[derive(Clone)]
 struct SPrimObjectHeader {
     header: SPrimHeader,
     header_flags: u32,
@@ -516,70 +592,98 @@ impl SPrimObjectHeader {
         reader: &mut Cursor<Vec<u8>>,
         input_version: &GameVersion,
         verbose: bool,
-    ) {
+    ) -> log::Result<()> {
+        // Linked objects are attached to a bone with fields of an unknown layout, reading them as
+        // static meshes would drop the link.
+        if self.is_linked() {
+            return Err(log::error(String::from(
+                "Error: Linked PRIMs are not supported, the layout of their bone links is not known",
+            )));
+        }
         for o in 0..self.num_objects {
             reader
                 .seek(SeekFrom::Start((self.object_table + o * 4) as u64))
                 .unwrap();
-            let object_offset = reader.read_u32::<LittleEndian>().unwrap();
+            layout::set_object(None);
+            let object_offset = layout::mark(Block::ObjectTable, reader, |reader| {
+                reader.read_u32::<LittleEndian>().unwrap()
+            });
+            layout::set_object(Some(o));
+            reader.seek(SeekFrom::Start(object_offset as u64)).unwrap();
+            let prim_type = SPrimHeader::read(reader).prim_type;
             reader.seek(SeekFrom::Start(object_offset as u64)).unwrap();
-            if self.is_weighted() {
-                let object = SPrimMeshWeighted::read(reader, self.header_flags, &input_version);
+            if prim_type == EPrimType::Shape as u16 {
+                return Err(log::error(format!(
+                    "Error: Object {} at offset {:#x} is a shape, the layout of shapes is not known",
+                    o, object_offset
+                ))
+                .at(object_offset as u64));
+            } else if prim_type != EPrimType::Mesh as u16 {
+                return Err(log::error(format!(
+                    "Error: Object {} at offset {:#x} has unknown prim_type {:#x}",
+                    o, object_offset, prim_type
+                ))
+                .at(object_offset as u64));
+            } else if self.is_weighted() {
+                let object = SPrimMeshWeighted::read(reader, self.header_flags, input_version)?;
                 if verbose {
                     object.print();
                 }
-                self.objects.push(Object::SPrimMeshWeighted(object));
+                self.objects.push(Object::Weighted(object));
             } else {
-                let object: SPrimMesh = SPrimMesh::read(reader, self.header_flags, &input_version);
+                let object: SPrimMesh = SPrimMesh::read(reader, self.header_flags, input_version)?;
                 if verbose {
                     object.print();
                 }
-                self.objects.push(Object::SPrimMesh(object));
+                self.objects.push(Object::Mesh(object));
+            }
+        }
+        layout::set_object(None);
+        // Only sub-meshes read by more than one object stay marked as shared.
+        let offsets: Vec<u32> = self
+            .objects
+            .iter()
+            .filter_map(|object| object.mesh().shared_sub_mesh)
+            .collect();
+        for object in &mut self.objects {
+            let mesh = object.mesh_mut();
+            if let Some(offset) = mesh.shared_sub_mesh {
+                if offsets.iter().filter(|&&other| other == offset).count() < 2 {
+                    mesh.shared_sub_mesh = None;
+                }
             }
         }
+        Ok(())
     }
 
-    fn write(
-        &mut self,
-        writer: &mut Cursor<Vec<u8>>,
-        output_version: &GameVersion,
-        no_cloth: &bool,
-    ) -> u32 {
+    fn write(&self, writer: &mut Cursor<Vec<u8>>, output_version: &GameVersion) -> u32 {
         let mut object_offsets = Vec::new();
-        for object in &mut self.objects {
+        let mut shared_sub_meshes = HashMap::new();
+        for (o, object) in self.objects.iter().enumerate() {
+            let sub_mesh_table = self.write_shared_sub_mesh(
+                writer,
+                object.mesh(),
+                o,
+                &mut shared_sub_meshes,
+                output_version,
+            );
             match object {
-                Object::SPrimMesh(object) => {
-                    object.object.header.draw_destination = SPrimObjectHeader::get_draw_destination(
-                        self.header_flags,
-                        object.object.header.draw_destination,
-                        output_version,
-                    );
+                Object::Mesh(object) => {
                     object_offsets.push(object.write(
                         writer,
                         self.header_flags,
-                        &output_version,
-                        0xFFFFFFFF,
+                        output_version,
+                        sub_mesh_table,
                     ));
                     io::align(writer, 0x10);
                 }
-                Object::SPrimMeshWeighted(object) => {
-                    if !no_cloth
-                        || (object.mesh.sub_mesh.as_ref().unwrap().offset_cloth > 0
-                            || (object.mesh.sub_mesh.as_ref().unwrap().offset_cloth == 0
-                                && object.mesh.cloth_id == 0))
-                    {
-                        object.mesh.object.header.draw_destination =
-                            SPrimObjectHeader::get_draw_destination(
-                                self.header_flags,
-                                object.mesh.object.header.draw_destination,
-                                output_version,
-                            );
-                        object_offsets.push(object.write(
-                            writer,
-                            self.header_flags,
-                            &output_version,
-                        ));
-                    }
+                Object::Weighted(object) => {
+                    object_offsets.push(object.write(
+                        writer,
+                        self.header_flags,
+                        output_version,
+                        sub_mesh_table,
+                    ));
                 }
             }
         }
@@ -602,38 +706,79 @@ impl SPrimObjectHeader {
         main_offset
     }
 
-    fn get_draw_destination(
-        header_flags: u32,
-        draw_destination: u8,
+    /// Writes the sub-mesh of a mesh shared with other objects the first time it is written, and
+    /// returns the offset of its sub-mesh table, or 0xFFFFFFFF when the mesh writes its own copy.
+    /// Objects keep sharing the sub-mesh as long as it is written the same for each of them.
+    fn write_shared_sub_mesh(
+        &self,
+        writer: &mut Cursor<Vec<u8>>,
+        mesh: &SPrimMesh,
+        o: usize,
+        written: &mut HashMap<u32, (u32, Vec<u8>)>,
         output_version: &GameVersion,
-    ) -> u8 {
-        match output_version {
-            GameVersion::Alpha => {
-                if header_flags & 8 == 8 {
-                    draw_destination & 0xF
-                } else {
-                    0x81
-                }
+    ) -> u32 {
+        let Some(offset) = mesh.shared_sub_mesh else {
+            return 0xFFFFFFFF;
+        };
+        let mut scratch = Cursor::new(Vec::new());
+        mesh.write_sub_mesh(&mut scratch, self.header_flags, output_version);
+        let data = scratch.into_inner();
+        match written.get(&offset) {
+            Some((sub_mesh_table, written_data)) if *written_data == data => *sub_mesh_table,
+            Some(_) => {
+                log!(
+                    "Warning: Object {} no longer matches the sub-mesh it shares at {:#x}, writing a copy",
+                    o, offset
+                );
+                0xFFFFFFFF
+            }
+            None => {
+                let sub_mesh_table = mesh.write_sub_mesh(writer, self.header_flags, output_version);
+                written.insert(offset, (sub_mesh_table, data));
+                sub_mesh_table
             }
-            _ => draw_destination & 0xF,
         }
     }
 
     fn print(&self) {
         self.header.print();
-        println!("header_flags: {:#x}", self.header_flags);
-        println!(
+        log!("header_flags: {:#x}", self.header_flags);
+        log!(
             "bone_rig_resource_index: {:#x}",
             self.bone_rig_resource_index
         );
-        println!("num_objects: {:#x}", self.num_objects);
-        println!("object_table: {:#x}", self.object_table);
-        println!("bounding_box_min: {:#x?}", self.bounding_box_min);
-        println!("bounding_box_max: {:#x?}", self.bounding_box_max);
+        log!("num_objects: {:#x}", self.num_objects);
+        log!("object_table: {:#x}", self.object_table);
+        log!("bounding_box_min: {:#x?}", self.bounding_box_min);
+        log!("bounding_box_max: {:#x?}", self.bounding_box_max);
     }
 
     fn is_weighted(&self) -> bool {
-        return self.header_flags & 8 == 8;
+        self.header_flags & 8 == 8
+    }
+
+    fn is_linked(&self) -> bool {
+        self.header_flags & ObjectHeaderFlags::IsLinkedObject as u32 != 0
+    }
+
+    fn kind(&self) -> &'static str {
+        if self.is_weighted() {
+            "weighted"
+        } else {
+            "static"
+        }
+    }
+
+    fn update_bounding_box(&mut self) {
+        self.bounding_box_min = [f32::MAX; 3];
+        self.bounding_box_max = [f32::MIN; 3];
+        for object in &self.objects {
+            let object = object.object();
+            for i in 0..3 {
+                self.bounding_box_min[i] = self.bounding_box_min[i].min(object.bounding_box_min[i]);
+                self.bounding_box_max[i] = self.bounding_box_max[i].max(object.bounding_box_max[i]);
+            }
+        }
     }
 }
 
@@ -648,6 +793,13 @@ enum SubType {
     SpeedTree = 6,
 }
 
+/// Turns the sub_types of extra UV channels back into Standard, keeping Linked and Weighted.
+fn single_uv_sub_type(sub_type: &mut u8) {
+    if (SubType::StandardUv2 as u8..=SubType::StandardUv4 as u8).contains(sub_type) {
+        *sub_type = SubType::Standard as u8;
+    }
+}
+
 #[repr(u8)]
 enum ObjectFlags {
     None = 0,
@@ -660,6 +812,7 @@ enum ObjectFlags {
     IsNoPhysicsProp = 0x40,
 }
 
+#[derive(Clone)]
 struct SPrimObject {
     header: SPrimHeader,
     sub_type: u8,
@@ -711,8 +864,8 @@ impl SPrimObject {
         writer.write_u16::<LittleEndian>(self.material_id).unwrap();
         writer.write_u32::<LittleEndian>(self.wire_color).unwrap();
         let color1 = {
-            if self.color1.is_some() {
-                *self.color1.as_ref().unwrap()
+            if let Some(color1) = &self.color1 {
+                *color1
             } else {
                 0
             }
@@ -727,21 +880,25 @@ impl SPrimObject {
         io::write_f32_into(writer, &self.bounding_box_max);
     }
 
+    fn has_flag(&self, flag: ObjectFlags) -> bool {
+        self.flags & flag as u8 != 0
+    }
+
     fn print(&self) {
         self.header.print();
-        println!("sub_type: {:#x}", self.sub_type);
-        println!("flags: {:#x}", self.flags);
-        println!("lod_mask: {:#x}", self.lod_mask);
-        println!("variant_id: {:#x}", self.variant_id);
-        println!("bias: {:#x}", self.bias);
-        println!("offset: {:#x}", self.offset);
-        println!("material_id: {:#x}", self.material_id);
-        println!("wire_color: {:#x}", self.wire_color);
-        if self.color1.is_some() {
-            println!("color1: {:#x}", self.color1.as_ref().unwrap());
+        log!("sub_type: {:#x}", self.sub_type);
+        log!("flags: {:#x}", self.flags);
+        log!("lod_mask: {:#x}", self.lod_mask);
+        log!("variant_id: {:#x}", self.variant_id);
+        log!("bias: {:#x}", self.bias);
+        log!("offset: {:#x}", self.offset);
+        log!("material_id: {:#x}", self.material_id);
+        log!("wire_color: {:#x}", self.wire_color);
+        if let Some(color1) = &self.color1 {
+            log!("color1: {:#x}", color1);
         }
-        println!("bounding_box_min: {:#x?}", self.bounding_box_min);
-        println!("bounding_box_max: {:#x?}", self.bounding_box_max);
+        log!("bounding_box_min: {:#x?}", self.bounding_box_min);
+        log!("bounding_box_max: {:#x?}", self.bounding_box_max);
     }
 }
 
@@ -750,6 +907,7 @@ enum ClothFlags {
     Small = 0x80,
 }
 
+#[derive(Clone)]
 struct SPrimMesh {
     object: SPrimObject,
     sub_mesh_table: u32,
@@ -760,6 +918,8 @@ struct SPrimMesh {
     uv_bias: [f32; 2],
     cloth_id: u8,
     pad: [u8; 3],
+    /// Offset of the sub-mesh when other objects of the PRIM share it
+    shared_sub_mesh: Option<u32>,
 }
 
 impl SPrimMesh {
@@ -767,9 +927,9 @@ impl SPrimMesh {
         reader: &mut Cursor<Vec<u8>>,
         header_flags: u32,
         input_version: &GameVersion,
-    ) -> SPrimMesh {
-        let mut mesh = SPrimMesh {
-            object: SPrimObject::read(reader, &input_version),
+    ) -> log::Result<SPrimMesh> {
+        let mut mesh = layout::mark(Block::Object, reader, |reader| SPrimMesh {
+            object: SPrimObject::read(reader, input_version),
             sub_mesh_table: reader.read_u32::<LittleEndian>().unwrap(),
             sub_mesh: None,
             position_scale: io::read_f32_array(reader, 4).try_into().unwrap(),
@@ -778,21 +938,25 @@ impl SPrimMesh {
             uv_bias: io::read_f32_array(reader, 2).try_into().unwrap(),
             cloth_id: reader.read_u8().unwrap(),
             pad: io::read_u8_array(reader, 3).try_into().unwrap(),
-        };
+            shared_sub_mesh: None,
+        });
         let position = reader.position();
         reader
             .seek(SeekFrom::Start(mesh.sub_mesh_table as u64))
             .unwrap();
-        let object_offset = reader.read_u32::<LittleEndian>().unwrap();
+        let object_offset = layout::mark(Block::SubMeshTable, reader, |reader| {
+            reader.read_u32::<LittleEndian>().unwrap()
+        });
         reader.seek(SeekFrom::Start(object_offset as u64)).unwrap();
+        mesh.shared_sub_mesh = Some(object_offset);
         mesh.sub_mesh = Some(SPrimSubMesh::read(
             reader,
             header_flags,
             &mesh,
-            &input_version,
-        ));
+            input_version,
+        )?);
         reader.seek(SeekFrom::Start(position)).unwrap();
-        mesh
+        Ok(mesh)
     }
 
     fn write_sub_mesh(
@@ -801,11 +965,8 @@ impl SPrimMesh {
         header_flags: u32,
         output_version: &GameVersion,
     ) -> u32 {
-        if self.sub_mesh.is_some() {
-            self.sub_mesh
-                .as_ref()
-                .unwrap()
-                .write(writer, header_flags, &self, output_version)
+        if let Some(sub_mesh) = &self.sub_mesh {
+            sub_mesh.write(writer, header_flags, self, output_version)
         } else {
             0
         }
@@ -823,7 +984,7 @@ impl SPrimMesh {
                 self.sub_mesh
                     .as_ref()
                     .unwrap()
-                    .write(writer, header_flags, &self, output_version)
+                    .write(writer, header_flags, self, output_version)
             } else {
                 sub_mesh_table
             }
@@ -843,19 +1004,35 @@ impl SPrimMesh {
         offset
     }
 
+    fn drop_extra_uv_channels(&mut self) -> bool {
+        let sub_mesh = self.sub_mesh.as_mut().unwrap();
+        let Some(vertices) = sub_mesh.vertices.as_mut() else {
+            return false;
+        };
+        if vertices.uvs.len() <= 1 {
+            return false;
+        }
+        vertices.uvs.truncate(1);
+        sub_mesh.num_uv_channels = 1;
+        single_uv_sub_type(&mut sub_mesh.object.sub_type);
+        single_uv_sub_type(&mut self.object.sub_type);
+        true
+    }
+
     fn print(&self) {
         self.object.print();
-        println!("sub_mesh_table: {:#x}", self.sub_mesh_table);
+        log!("sub_mesh_table: {:#x}", self.sub_mesh_table);
         self.sub_mesh.as_ref().unwrap().print();
-        println!("position_scale: {:#x?}", self.position_scale);
-        println!("position_bias: {:#x?}", self.position_bias);
-        println!("uv_scale: {:#x?}", self.uv_scale);
-        println!("uv_bias: {:#x?}", self.uv_bias);
-        println!("cloth_id: {:#x}", self.cloth_id);
-        println!("pad: {:#x?}", self.pad);
+        log!("position_scale: {:#x?}", self.position_scale);
+        log!("position_bias: {:#x?}", self.position_bias);
+        log!("uv_scale: {:#x?}", self.uv_scale);
+        log!("uv_bias: {:#x?}", self.uv_bias);
+        log!("cloth_id: {:#x}", self.cloth_id);
+        log!("pad: {:#x?}", self.pad);
     }
 }
 
+#[derive(Clone)]
 struct SPrimMeshWeighted {
     mesh: SPrimMesh,
     num_copy_bones: u32,
@@ -872,9 +1049,10 @@ impl SPrimMeshWeighted {
         reader: &mut Cursor<Vec<u8>>,
         header_flags: u32,
         input_version: &GameVersion,
-    ) -> SPrimMeshWeighted {
-        let mut mesh = SPrimMeshWeighted {
-            mesh: SPrimMesh::read(reader, header_flags, &input_version),
+    ) -> log::Result<SPrimMeshWeighted> {
+        let mesh = SPrimMesh::read(reader, header_flags, input_version)?;
+        let mut mesh = layout::mark(Block::Object, reader, |reader| SPrimMeshWeighted {
+            mesh,
             num_copy_bones: reader.read_u32::<LittleEndian>().unwrap(),
             offset_copy_bones: reader.read_u32::<LittleEndian>().unwrap(),
             copy_bones: None,
@@ -882,26 +1060,30 @@ impl SPrimMeshWeighted {
             bone_indicies: None,
             offset_bone_info: reader.read_u32::<LittleEndian>().unwrap(),
             bone_info: None,
-        };
+        });
         if mesh.num_copy_bones > 0 && mesh.offset_copy_bones > 0 {
             reader
                 .seek(SeekFrom::Start(mesh.offset_copy_bones as u64))
                 .unwrap();
-            mesh.copy_bones = Some(CopyBones::read(reader, mesh.num_copy_bones));
+            mesh.copy_bones = Some(layout::mark(Block::CopyBones, reader, |reader| {
+                CopyBones::read(reader, mesh.num_copy_bones)
+            }));
         }
         if mesh.offset_bone_indicies > 0 {
             reader
                 .seek(SeekFrom::Start(mesh.offset_bone_indicies as u64))
                 .unwrap();
-            mesh.bone_indicies = Some(BoneIndices::read(reader, &input_version));
+            mesh.bone_indicies = Some(layout::mark(Block::BoneIndices, reader, |reader| {
+                BoneIndices::read(reader, input_version)
+            }));
         }
         if mesh.offset_bone_info > 0 {
             reader
                 .seek(SeekFrom::Start(mesh.offset_bone_info as u64))
                 .unwrap();
-            mesh.bone_info = Some(BoneInfo::read(reader));
+            mesh.bone_info = Some(layout::mark(Block::BoneInfo, reader, BoneInfo::read));
         }
-        mesh
+        Ok(mesh)
     }
 
     fn write(
@@ -909,34 +1091,35 @@ impl SPrimMeshWeighted {
         writer: &mut Cursor<Vec<u8>>,
         header_flags: u32,
         output_version: &GameVersion,
+        sub_mesh_table: u32,
     ) -> u32 {
-        let sub_mesh_table = self
-            .mesh
-            .write_sub_mesh(writer, header_flags, &output_version);
+        let sub_mesh_table = match sub_mesh_table {
+            0xFFFFFFFF => self
+                .mesh
+                .write_sub_mesh(writer, header_flags, output_version),
+            _ => sub_mesh_table,
+        };
         let mut offset_copy_bones = 0;
-        if self.copy_bones.is_some() {
+        if let Some(copy_bones) = &self.copy_bones {
             offset_copy_bones = writer.position() as u32;
-            self.copy_bones.as_ref().unwrap().write(writer);
+            copy_bones.write(writer);
         }
         io::align(writer, 0x10);
         let mut offset_bone_info = 0;
-        if self.bone_info.is_some() {
+        if let Some(bone_info) = &self.bone_info {
             offset_bone_info = writer.position() as u32;
-            self.bone_info.as_ref().unwrap().write(writer);
+            bone_info.write(writer);
         }
         io::align(writer, 0x10);
         let mut offset_bone_indicies = 0;
-        if self.bone_indicies.is_some() {
+        if let Some(bone_indicies) = &self.bone_indicies {
             offset_bone_indicies = writer.position() as u32;
-            self.bone_indicies
-                .as_ref()
-                .unwrap()
-                .write(writer, &output_version);
+            bone_indicies.write(writer, output_version);
         }
         io::align(writer, 0x10);
         let offset = writer.position() as u32;
         self.mesh
-            .write(writer, header_flags, &output_version, sub_mesh_table);
+            .write(writer, header_flags, output_version, sub_mesh_table);
         writer
             .write_u32::<LittleEndian>(self.num_copy_bones)
             .unwrap();
@@ -951,22 +1134,23 @@ impl SPrimMeshWeighted {
 
     fn print(&self) {
         self.mesh.print();
-        println!("num_copy_bones: {:#x}", self.num_copy_bones);
-        println!("offset_copy_bones: {:#x}", self.offset_copy_bones);
-        if self.copy_bones.is_some() {
-            self.copy_bones.as_ref().unwrap().print();
+        log!("num_copy_bones: {:#x}", self.num_copy_bones);
+        log!("offset_copy_bones: {:#x}", self.offset_copy_bones);
+        if let Some(copy_bones) = &self.copy_bones {
+            copy_bones.print();
         }
-        println!("offset_bone_indicies: {:#x}", self.offset_bone_indicies);
-        if self.bone_indicies.is_some() {
-            self.bone_indicies.as_ref().unwrap().print();
+        log!("offset_bone_indicies: {:#x}", self.offset_bone_indicies);
+        if let Some(bone_indicies) = &self.bone_indicies {
+            bone_indicies.print();
         }
-        println!("offset_bone_info: {:#x}", self.offset_bone_info);
-        if self.bone_info.is_some() {
-            self.bone_info.as_ref().unwrap().print();
+        log!("offset_bone_info: {:#x}", self.offset_bone_info);
+        if let Some(bone_info) = &self.bone_info {
+            bone_info.print();
         }
     }
 }
 
+#[derive(Clone)]
 struct SPrimSubMesh {
     object: SPrimObject,
     num_vertices: u32,
@@ -989,9 +1173,9 @@ impl SPrimSubMesh {
         header_flags: u32,
         mesh: &SPrimMesh,
         input_version: &GameVersion,
-    ) -> SPrimSubMesh {
-        let mut sub_mesh = SPrimSubMesh {
-            object: SPrimObject::read(reader, &input_version),
+    ) -> log::Result<SPrimSubMesh> {
+        let mut sub_mesh = layout::mark(Block::SubMesh, reader, |reader| SPrimSubMesh {
+            object: SPrimObject::read(reader, input_version),
             num_vertices: reader.read_u32::<LittleEndian>().unwrap(),
             offset_vertices: reader.read_u32::<LittleEndian>().unwrap(),
             vertices: None,
@@ -1011,24 +1195,33 @@ impl SPrimSubMesh {
             offset_cloth: reader.read_u32::<LittleEndian>().unwrap(),
             cloth: None,
             num_uv_channels: reader.read_u32::<LittleEndian>().unwrap(),
-        };
-        if sub_mesh.num_vertices > 0 && sub_mesh.offset_vertices > 0 {
+        });
+        // PS3 Edge compresses the vertex and index streams, which are left unread so the PRIM can
+        // still be inspected. Converting it is rejected.
+        let is_ps3_edge = mesh.object.has_flag(ObjectFlags::Ps3Edge)
+            || sub_mesh.object.has_flag(ObjectFlags::Ps3Edge);
+        sub_mesh.check_speed_tree(mesh)?;
+        if !is_ps3_edge && sub_mesh.num_vertices > 0 && sub_mesh.offset_vertices > 0 {
             reader
                 .seek(SeekFrom::Start(sub_mesh.offset_vertices as u64))
                 .unwrap();
-            sub_mesh.vertices = Some(Vertices::read(reader, header_flags, mesh, &sub_mesh));
+            sub_mesh.vertices = Some(layout::mark(Block::Vertices, reader, |reader| {
+                Vertices::read(reader, header_flags, mesh, &sub_mesh)
+            }));
         }
-        if sub_mesh.num_indices > 0 && sub_mesh.offset_indices > 0 {
+        if !is_ps3_edge && sub_mesh.num_indices > 0 && sub_mesh.offset_indices > 0 {
             reader
                 .seek(SeekFrom::Start(sub_mesh.offset_indices as u64))
                 .unwrap();
-            sub_mesh.indices = Some(Indices::read(reader, &sub_mesh));
+            sub_mesh.indices = Some(layout::mark(Block::Indices, reader, |reader| {
+                Indices::read(reader, &sub_mesh)
+            }));
         }
         if sub_mesh.offset_collision > 0 {
             reader
                 .seek(SeekFrom::Start(sub_mesh.offset_collision as u64))
                 .unwrap();
-            sub_mesh.collision = Some(Collision::read(reader));
+            sub_mesh.collision = Some(layout::mark(Block::Collision, reader, Collision::read));
         }
         if sub_mesh.offset_cloth > 0 {
             match input_version {
@@ -1036,14 +1229,29 @@ impl SPrimSubMesh {
                     reader
                         .seek(SeekFrom::Start(sub_mesh.offset_cloth as u64))
                         .unwrap();
-                    sub_mesh.cloth = Some(Cloth::read(reader, &sub_mesh, mesh.cloth_id));
+                    sub_mesh.cloth = Some(layout::mark(Block::Cloth, reader, |reader| {
+                        Cloth::read(reader, &sub_mesh, mesh.cloth_id)
+                    }));
                 }
                 _ => (),
             }
         }
-        sub_mesh
+        Ok(sub_mesh)
     }
 
+    /// The SpeedTree vertex layout is not known, reading it as the standard layout would garble
+    /// the mesh.
+    fn check_speed_tree(&self, mesh: &SPrimMesh) -> log::Result<()> {
+        let speed_tree = SubType::SpeedTree as u8;
+        if mesh.object.sub_type == speed_tree || self.object.sub_type == speed_tree {
+            return Err(log::error(format!(
+                "Error: Sub-mesh at offset {:#x} is a SpeedTree mesh, whose vertex layout is not supported",
+                self.offset_vertices
+            ))
+            .at(self.offset_vertices as u64));
+        }
+        Ok(())
+    }
     fn write(
         &self,
         writer: &mut Cursor<Vec<u8>>,
@@ -1052,30 +1260,27 @@ impl SPrimSubMesh {
         output_version: &GameVersion,
     ) -> u32 {
         let mut offset_indices = 0;
-        if self.indices.is_some() {
+        if let Some(indices) = &self.indices {
             offset_indices = writer.position() as u32;
-            self.indices.as_ref().unwrap().write(writer);
+            indices.write(writer);
         }
         io::align(writer, 0x10);
         let mut offset_vertices = 0;
-        if self.vertices.is_some() {
+        if let Some(vertices) = &self.vertices {
             offset_vertices = writer.position() as u32;
-            self.vertices
-                .as_ref()
-                .unwrap()
-                .write(writer, header_flags, mesh, &self);
+            vertices.write(writer, header_flags, mesh, self);
         }
         io::align(writer, 0x10);
         let mut offset_collision = 0;
-        if self.collision.is_some() {
+        if let Some(collision) = &self.collision {
             offset_collision = writer.position() as u32;
-            self.collision.as_ref().unwrap().write(writer);
+            collision.write(writer);
         }
         io::align(writer, 0x10);
         let mut offset_cloth = 0;
-        if self.cloth.is_some() {
+        if let Some(cloth) = &self.cloth {
             offset_cloth = writer.position() as u32;
-            self.cloth.as_ref().unwrap().write(writer);
+            cloth.write(writer);
         }
         io::align(writer, 0x10);
         let offset_object = writer.position() as u32;
@@ -1084,8 +1289,8 @@ impl SPrimSubMesh {
         writer.write_u32::<LittleEndian>(offset_vertices).unwrap();
         writer.write_u32::<LittleEndian>(self.num_indices).unwrap();
         let num_indices_extra = {
-            if self.num_indices_extra.is_some() {
-                *self.num_indices_extra.as_ref().unwrap()
+            if let Some(num_indices_extra) = &self.num_indices_extra {
+                *num_indices_extra
             } else {
                 0
             }
@@ -1099,18 +1304,9 @@ impl SPrimSubMesh {
         writer.write_u32::<LittleEndian>(offset_indices).unwrap();
         writer.write_u32::<LittleEndian>(offset_collision).unwrap();
         writer.write_u32::<LittleEndian>(offset_cloth).unwrap();
-        let is_weighted = header_flags & 8 == 8;
-        let num_uv_channels = match output_version {
-            GameVersion::Hma | GameVersion::Alpha | GameVersion::Hm2016 => {
-                if is_weighted {
-                    0
-                } else {
-                    1
-                }
-            }
-            GameVersion::Woa => 1,
-        };
-        writer.write_u32::<LittleEndian>(num_uv_channels).unwrap();
+        writer
+            .write_u32::<LittleEndian>(self.num_uv_channels)
+            .unwrap();
         io::align(writer, 0x10);
         let offset = writer.position() as u32;
         writer.write_u32::<LittleEndian>(offset_object).unwrap();
@@ -1118,78 +1314,209 @@ impl SPrimSubMesh {
         offset
     }
 
+    fn uv_channels(&self) -> usize {
+        let sub_type_channels = match self.object.sub_type {
+            x if x == SubType::StandardUv2 as u8 => 2,
+            x if x == SubType::StandardUv3 as u8 => 3,
+            x if x == SubType::StandardUv4 as u8 => 4,
+            _ => 1,
+        };
+        let vertex_channels = match &self.vertices {
+            Some(vertices) => vertices.uvs.len(),
+            None => self.num_uv_channels as usize,
+        };
+        sub_type_channels.max(vertex_channels).max(1)
+    }
+
     fn print(&self) {
         self.object.print();
-        println!("num_vertices: {:#x}", self.num_vertices);
-        println!("offset_vertices: {:#x}", self.offset_vertices);
-        if self.vertices.is_some() {
-            self.vertices.as_ref().unwrap().print();
+        log!("num_vertices: {:#x}", self.num_vertices);
+        log!("offset_vertices: {:#x}", self.offset_vertices);
+        if let Some(vertices) = &self.vertices {
+            vertices.print();
         }
-        println!("num_indices: {:#x}", self.num_indices);
-        if self.num_indices_extra.is_some() {
-            println!(
-                "num_indices_extra: {:#x}",
-                self.num_indices_extra.as_ref().unwrap()
-            );
+        log!("num_indices: {:#x}", self.num_indices);
+        if let Some(num_indices_extra) = &self.num_indices_extra {
+            log!("num_indices_extra: {:#x}", num_indices_extra);
         }
-        println!("offset_indices: {:#x}", self.offset_indices);
-        if self.indices.is_some() {
-            self.indices.as_ref().unwrap().print();
+        log!("offset_indices: {:#x}", self.offset_indices);
+        if let Some(indices) = &self.indices {
+            indices.print();
         }
-        println!("offset_collision: {:#x}", self.offset_collision);
-        if self.collision.is_some() {
-            self.collision.as_ref().unwrap().print();
+        log!("offset_collision: {:#x}", self.offset_collision);
+        if let Some(collision) = &self.collision {
+            collision.print();
         }
-        println!("offset_cloth: {:#x}", self.offset_cloth);
-        if self.cloth.is_some() {
-            self.cloth.as_ref().unwrap().print();
+        log!("offset_cloth: {:#x}", self.offset_cloth);
+        if let Some(cloth) = &self.cloth {
+            cloth.print();
         }
-        println!("num_uv_channels: {:#x}", self.num_uv_channels);
+        log!("num_uv_channels: {:#x}", self.num_uv_channels);
     }
 }
 
+#[derive(Clone)]
 pub struct Prim {
     header: SPrimObjectHeader,
 }
 
 impl Prim {
-    pub fn read(path: &PathBuf, input_version: &GameVersion, verbose: bool) -> Prim {
+    pub fn read(path: &Path, input_version: &GameVersion, verbose: bool) -> log::Result<Prim> {
         let mut file = match File::open(path) {
             Ok(file) => file,
             Err(err) => {
-                println!("Error opening file {}: {}", path.to_str().unwrap(), err);
-                std::process::exit(1);
+                return Err(log::error(format!(
+                    "Error opening file {}: {}",
+                    path.to_str().unwrap(),
+                    err
+                ))
+                .in_file(path));
             }
         };
-        let mut buffer = vec![0 as u8; file.metadata().unwrap().len() as usize];
+        let mut buffer = vec![0_u8; file.metadata().unwrap().len() as usize];
         Read::read(&mut file, &mut buffer).unwrap();
-        let mut reader = Cursor::new(buffer);
-        let main_offset = reader.read_u32::<LittleEndian>().unwrap();
-        reader.seek(SeekFrom::Start(main_offset as u64)).unwrap();
-        let mut header = SPrimObjectHeader::read(&mut reader);
+        Prim::from_bytes(buffer, input_version, verbose).map_err(|err| err.in_file(path))
+    }
+
+    /// Reads a PRIM from the data of a resource, e.g. one read from an RPKG archive, warning
+    /// about data that is not ported as read.
+    pub fn from_bytes(
+        buffer: Vec<u8>,
+        input_version: &GameVersion,
+        verbose: bool,
+    ) -> log::Result<Prim> {
+        let (prim, coverage) = Prim::read_bytes(buffer, input_version, verbose)?;
         if verbose {
-            println!("Prim Main Header: {:#x}", main_offset);
-            header.print();
+            for shared in coverage.shared_structures() {
+                log!("{}", shared);
+            }
+        }
+        for issue in coverage.issues() {
+            log!("Warning: {}", issue);
         }
-        header.read_objects(&mut reader, &input_version, verbose);
-        Prim { header: header }
+        Ok(prim)
     }
 
-    pub fn write(&mut self, path: &PathBuf, output_version: &GameVersion, no_cloth: bool) {
+    /// Reads a PRIM along with the coverage of the bytes read.
+    fn read_bytes(
+        buffer: Vec<u8>,
+        input_version: &GameVersion,
+        verbose: bool,
+    ) -> log::Result<(Prim, Coverage)> {
+        let mut reader = Cursor::new(buffer);
+        layout::record();
+        // Reads panic on truncated or malformed data, report where reading stopped instead.
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            let main_offset = layout::mark(Block::File, &mut reader, |reader| {
+                reader.read_u32::<LittleEndian>().unwrap()
+            });
+            reader.seek(SeekFrom::Start(main_offset as u64)).unwrap();
+            let mut header = layout::mark(Block::Header, &mut reader, SPrimObjectHeader::read);
+            if verbose {
+                log!("Prim Main Header: {:#x}", main_offset);
+                header.print();
+            }
+            header.read_objects(&mut reader, input_version, verbose)?;
+            Ok(header)
+        }));
+        match result {
+            Ok(Ok(header)) => Ok((Prim { header }, layout::finish(reader.get_ref()))),
+            Ok(Err(err)) => Err(err),
+            Err(payload) => Err(log::error(format!(
+                "Error: Failed to read PRIM at offset {:#x}: {}",
+                reader.position(),
+                log::panic_message(payload.as_ref())
+            ))
+            .at(reader.position())),
+        }
+    }
+
+    pub fn write(
+        &mut self,
+        path: &PathBuf,
+        output_version: &GameVersion,
+        profile: &Profile,
+    ) -> log::Result<()> {
+        // Converting can fail, build the PRIM before an existing output file is truncated.
+        let buffer = self.write_bytes(output_version, profile)?;
         let mut file = match File::create(path) {
             Ok(file) => file,
             Err(err) => {
-                println!("Error creating file {}: {}", path.to_str().unwrap(), err);
-                std::process::exit(1);
+                return Err(log::error(format!(
+                    "Error creating file {}: {}",
+                    path.to_str().unwrap(),
+                    err
+                ))
+                .in_file(path));
             }
         };
+        File::write(&mut file, &buffer).unwrap();
+        Ok(())
+    }
+
+    /// Converts and writes the PRIM into the data of a resource, e.g. one added to an RPKG archive.
+    pub fn write_bytes(
+        &mut self,
+        output_version: &GameVersion,
+        profile: &Profile,
+    ) -> log::Result<Vec<u8>> {
+        self.header.convert(output_version, profile)?;
         let buffer: Vec<u8> = Vec::new();
         let mut writer = Cursor::new(buffer);
         writer.write_u128::<LittleEndian>(0).unwrap();
-        let main_offset = self.header.write(&mut writer, &output_version, &no_cloth);
+        let main_offset = self.header.write(&mut writer, output_version);
         writer.seek(SeekFrom::Start(0)).unwrap();
         writer.write_u32::<LittleEndian>(main_offset).unwrap();
-        writer.seek(SeekFrom::Start(0)).unwrap();
-        File::write(&mut file, writer.get_ref()).unwrap();
+        Ok(writer.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Static PRIM of two objects reading the same sub-mesh table.
+    const SHARED: &[u8] = include_bytes!("../tests/fixtures/shared.prim");
+
+    fn read(buffer: &[u8]) -> (Prim, layout::Coverage) {
+        Prim::read_bytes(buffer.to_vec(), &GameVersion::Woa, false).unwrap()
+    }
+
+    fn write(prim: &mut Prim) -> Vec<u8> {
+        let profile = Profile::builtin(&GameVersion::Woa, &GameVersion::Woa);
+        prim.write_bytes(&GameVersion::Woa, &profile).unwrap()
+    }
+
+    fn shared_sub_meshes(prim: &Prim) -> Vec<Option<u32>> {
+        prim.header
+            .objects
+            .iter()
+            .map(|object| object.mesh().shared_sub_mesh)
+            .collect()
+    }
+
+    #[test]
+    fn writes_shared_sub_mesh_once() {
+        let (mut prim, coverage) = read(SHARED);
+        assert_eq!(coverage.shared.len(), 1);
+        assert_eq!(shared_sub_meshes(&prim), vec![Some(0x140), Some(0x140)]);
+        let written = write(&mut prim);
+        assert_eq!(written, SHARED);
+        let (prim, coverage) = read(&written);
+        assert_eq!(coverage.shared.len(), 1);
+        assert_eq!(shared_sub_meshes(&prim), vec![Some(0x140), Some(0x140)]);
+    }
+
+    #[test]
+    fn writes_copy_of_changed_shared_sub_mesh() {
+        let (mut prim, _) = read(SHARED);
+        let mesh = prim.header.objects[1].mesh_mut();
+        let vertices = mesh.sub_mesh.as_mut().unwrap().vertices.as_mut().unwrap();
+        vertices.colors[0] = [1, 2, 3, 4];
+        let written = write(&mut prim);
+        assert!(written.len() > SHARED.len());
+        let (prim, coverage) = read(&written);
+        assert!(coverage.shared.is_empty());
+        assert_eq!(shared_sub_meshes(&prim), vec![None, None]);
     }
 }
@@ -0,0 +1,396 @@
+use super::{ObjectFlags, Prim, SPrimMesh, SPrimSubMesh};
+use crate::GameVersion;
+
+#[derive(Clone, Copy)]
+pub enum ColorOperation {
+    /// Sets every vertex color and color1 to a constant RGBA color
+    Set([u8; 4]),
+    /// Expands color1 into a per-vertex color stream
+    Color1ToColors,
+    /// Bakes ambient occlusion with the given number of rays per vertex into the color alpha
+    BakeAmbientOcclusion(u32),
+    /// Replaces uniform per-vertex color streams with color1
+    ColorsToColor1,
+}
+
+impl Prim {
+    pub fn apply_color_operation(
+        &mut self,
+        operation: &ColorOperation,
+        output_version: &GameVersion,
+    ) {
+        let is_weighted = self.header.is_weighted();
+        let mut count = 0;
+        for mesh in self
+            .header
+            .objects
+            .iter_mut()
+            .map(|object| object.mesh_mut())
+        {
+            let changed = match operation {
+                ColorOperation::Set(color) => mesh.set_colors(*color, is_weighted),
+                ColorOperation::Color1ToColors => mesh.color1_to_colors(is_weighted),
+                ColorOperation::BakeAmbientOcclusion(samples) => {
+                    mesh.color1_to_colors(is_weighted);
+                    mesh.bake_ambient_occlusion(*samples)
+                }
+                ColorOperation::ColorsToColor1 => match output_version {
+                    GameVersion::Hma | GameVersion::Alpha => {
+                        log!("Warning: Output game version cannot store color1, keeping per-vertex colors");
+                        return;
+                    }
+                    GameVersion::Hm2016 | GameVersion::Woa => mesh.colors_to_color1(is_weighted),
+                },
+            };
+            if changed {
+                count += 1;
+            }
+        }
+        match operation {
+            ColorOperation::Set(_) => log!("Set colors of {} sub-meshes", count),
+            ColorOperation::Color1ToColors => {
+                log!("Expanded color1 of {} sub-meshes into vertex colors", count)
+            }
+            ColorOperation::BakeAmbientOcclusion(_) => {
+                log!("Baked ambient occlusion into {} sub-meshes", count)
+            }
+            ColorOperation::ColorsToColor1 => {
+                log!("Converted vertex colors of {} sub-meshes to color1", count)
+            }
+        }
+    }
+}
+
+impl SPrimMesh {
+    /// Returns the color1 value used instead of a per-vertex color stream, if any.
+    fn color1(&self, is_weighted: bool) -> Option<u32> {
+        let sub_mesh = self.sub_mesh.as_ref().unwrap();
+        if !is_weighted && self.object.flags & ObjectFlags::Color1 as u8 != 0 {
+            Some(self.object.color1.or(sub_mesh.object.color1).unwrap_or(0))
+        } else if sub_mesh.object.flags & ObjectFlags::Color1 as u8 != 0 {
+            Some(sub_mesh.object.color1.unwrap_or(0))
+        } else {
+            None
+        }
+    }
+
+    fn set_colors(&mut self, color: [u8; 4], is_weighted: bool) -> bool {
+        let color1 = self.color1(is_weighted);
+        let sub_mesh = self.sub_mesh.as_mut().unwrap();
+        let Some(vertices) = sub_mesh.vertices.as_mut() else {
+            return false;
+        };
+        if color1.is_some() {
+            if self.object.flags & ObjectFlags::Color1 as u8 != 0 {
+                self.object.color1 = Some(u32::from_le_bytes(color));
+            }
+            sub_mesh.object.color1 = Some(u32::from_le_bytes(color));
+        }
+        vertices.colors.iter_mut().for_each(|x| *x = color);
+        true
+    }
+
+    fn color1_to_colors(&mut self, is_weighted: bool) -> bool {
+        let Some(color1) = self.color1(is_weighted) else {
+            return false;
+        };
+        let sub_mesh = self.sub_mesh.as_mut().unwrap();
+        let Some(vertices) = sub_mesh.vertices.as_mut() else {
+            return false;
+        };
+        vertices.colors = vec![color1.to_le_bytes(); sub_mesh.num_vertices as usize];
+        self.object.flags &= !(ObjectFlags::Color1 as u8);
+        sub_mesh.object.flags &= !(ObjectFlags::Color1 as u8);
+        true
+    }
+
+    fn colors_to_color1(&mut self, is_weighted: bool) -> bool {
+        if self.color1(is_weighted).is_some() {
+            return false;
+        }
+        let sub_mesh = self.sub_mesh.as_mut().unwrap();
+        let Some(vertices) = sub_mesh.vertices.as_ref() else {
+            return false;
+        };
+        let Some(color) = vertices.colors.first() else {
+            return false;
+        };
+        if vertices.colors.iter().any(|x| x != color) {
+            return false;
+        }
+        sub_mesh.object.color1 = Some(u32::from_le_bytes(*color));
+        sub_mesh.object.flags |= ObjectFlags::Color1 as u8;
+        true
+    }
+
+    fn bake_ambient_occlusion(&mut self, samples: u32) -> bool {
+        let sub_mesh = self.sub_mesh.as_mut().unwrap();
+        let bvh = Bvh::new(triangles(sub_mesh));
+        let Some(vertices) = sub_mesh.vertices.as_mut() else {
+            return false;
+        };
+        if vertices.colors.len() != vertices.positions.len() {
+            return false;
+        }
+        let min = sub_mesh.object.bounding_box_min;
+        let max = sub_mesh.object.bounding_box_max;
+        let diagonal = (0..3)
+            .map(|i| (max[i] - min[i]) * (max[i] - min[i]))
+            .sum::<f32>()
+            .sqrt();
+        let epsilon = diagonal * 1e-4;
+        for (i, position) in vertices.positions.iter().enumerate() {
+            let normal = normalize([
+                vertices.normals[i][0],
+                vertices.normals[i][1],
+                vertices.normals[i][2],
+            ]);
+            let origin = [
+                position[0] + normal[0] * epsilon,
+                position[1] + normal[1] * epsilon,
+                position[2] + normal[2] * epsilon,
+            ];
+            let unoccluded = hemisphere(normal, samples)
+                .filter(|direction| !bvh.occludes(&origin, direction))
+                .count();
+            vertices.colors[i][3] = f32::round(255.0 * unoccluded as f32 / samples as f32) as u8;
+        }
+        true
+    }
+}
+
+fn triangles(sub_mesh: &SPrimSubMesh) -> Vec<[[f32; 3]; 3]> {
+    let (Some(vertices), Some(indices)) = (sub_mesh.vertices.as_ref(), sub_mesh.indices.as_ref())
+    else {
+        return Vec::new();
+    };
+    let position = |index: u16| {
+        let position = vertices.positions[index as usize];
+        [position[0], position[1], position[2]]
+    };
+    indices.indices[..sub_mesh.num_indices as usize]
+        .chunks_exact(3)
+        .map(|triangle| {
+            [
+                position(triangle[0]),
+                position(triangle[1]),
+                position(triangle[2]),
+            ]
+        })
+        .collect()
+}
+
+/// Triangles per leaf of the bounding volume hierarchy.
+const LEAF_SIZE: usize = 4;
+
+/// Bounding volume hierarchy over the triangles of a sub-mesh, so a ray is only tested against
+/// the triangles whose boxes it passes through.
+struct Bvh {
+    triangles: Vec<[[f32; 3]; 3]>,
+    nodes: Vec<Node>,
+}
+
+/// Box around a range of the triangles, split in two children unless it is a leaf.
+struct Node {
+    min: [f32; 3],
+    max: [f32; 3],
+    children: Option<(usize, usize)>,
+    start: usize,
+    end: usize,
+}
+
+impl Bvh {
+    fn new(mut triangles: Vec<[[f32; 3]; 3]>) -> Bvh {
+        let mut nodes = Vec::new();
+        if !triangles.is_empty() {
+            build(&mut nodes, &mut triangles, 0);
+        }
+        Bvh { triangles, nodes }
+    }
+
+    /// Whether a ray hits any triangle in front of its origin.
+    fn occludes(&self, origin: &[f32; 3], direction: &[f32; 3]) -> bool {
+        if self.nodes.is_empty() {
+            return false;
+        }
+        let inverse = direction.map(|x| 1.0 / x);
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if !hits_box(origin, &inverse, &node.min, &node.max) {
+                continue;
+            }
+            match node.children {
+                Some((left, right)) => stack.extend([left, right]),
+                None => {
+                    let triangles = &self.triangles[node.start..node.end];
+                    if triangles
+                        .iter()
+                        .any(|triangle| intersects(origin, direction, triangle))
+                    {
+                        return true;
+                    }
+                }
+            }
+        }
+        false
+    }
+}
+
+/// Adds the node of `triangles`, which start at `start` in the whole list, and its children,
+/// splitting at the median along the longest axis.
+fn build(nodes: &mut Vec<Node>, triangles: &mut [[[f32; 3]; 3]], start: usize) -> usize {
+    let mut min = [f32::MAX; 3];
+    let mut max = [f32::MIN; 3];
+    for point in triangles.iter().flatten() {
+        for i in 0..3 {
+            min[i] = min[i].min(point[i]);
+            max[i] = max[i].max(point[i]);
+        }
+    }
+    // Rays grazing a triangle in the face of a flat box miss the box by rounding, pad it.
+    let pad = (0..3).map(|i| max[i] - min[i]).fold(0.0, f32::max) * 1e-4 + 1e-6;
+    min = min.map(|x| x - pad);
+    max = max.map(|x| x + pad);
+    let index = nodes.len();
+    nodes.push(Node {
+        min,
+        max,
+        children: None,
+        start,
+        end: start + triangles.len(),
+    });
+    if triangles.len() > LEAF_SIZE {
+        let axis = (0..3)
+            .max_by(|&a, &b| (max[a] - min[a]).total_cmp(&(max[b] - min[b])))
+            .unwrap();
+        let center =
+            |triangle: &[[f32; 3]; 3]| triangle.iter().map(|point| point[axis]).sum::<f32>();
+        triangles.sort_by(|a, b| center(a).total_cmp(&center(b)));
+        let middle = triangles.len() / 2;
+        let (left, right) = triangles.split_at_mut(middle);
+        let left = build(nodes, left, start);
+        let right = build(nodes, right, start + middle);
+        nodes[index].children = Some((left, right));
+    }
+    index
+}
+
+/// Slab test of a ray against a box, from the inverse of its direction.
+fn hits_box(origin: &[f32; 3], inverse: &[f32; 3], min: &[f32; 3], max: &[f32; 3]) -> bool {
+    let mut near = 0.0_f32;
+    let mut far = f32::INFINITY;
+    for i in 0..3 {
+        let t1 = (min[i] - origin[i]) * inverse[i];
+        let t2 = (max[i] - origin[i]) * inverse[i];
+        near = near.max(t1.min(t2));
+        far = far.min(t1.max(t2));
+    }
+    near <= far
+}
+
+fn normalize(value: [f32; 3]) -> [f32; 3] {
+    let length = dot(&value, &value).sqrt();
+    if length > 0.0 {
+        [value[0] / length, value[1] / length, value[2] / length]
+    } else {
+        [0.0, 0.0, 1.0]
+    }
+}
+
+fn dot(a: &[f32; 3], b: &[f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: &[f32; 3], b: &[f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn sub(a: &[f32; 3], b: &[f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+/// Cosine weighted directions around the normal, spread with a Fibonacci spiral.
+fn hemisphere(normal: [f32; 3], samples: u32) -> impl Iterator<Item = [f32; 3]> {
+    let helper = if normal[0].abs() < 0.9 {
+        [1.0, 0.0, 0.0]
+    } else {
+        [0.0, 1.0, 0.0]
+    };
+    let tangent = normalize(cross(&helper, &normal));
+    let bitangent = cross(&normal, &tangent);
+    let golden_angle = std::f32::consts::PI * (3.0 - 5.0_f32.sqrt());
+    (0..samples).map(move |i| {
+        let r = ((i as f32 + 0.5) / samples as f32).sqrt();
+        let (sin, cos) = (i as f32 * golden_angle).sin_cos();
+        let (x, y, z) = (r * cos, r * sin, (1.0 - r * r).sqrt());
+        [
+            tangent[0] * x + bitangent[0] * y + normal[0] * z,
+            tangent[1] * x + bitangent[1] * y + normal[1] * z,
+            tangent[2] * x + bitangent[2] * y + normal[2] * z,
+        ]
+    })
+}
+
+/// Möller–Trumbore ray/triangle intersection.
+fn intersects(origin: &[f32; 3], direction: &[f32; 3], triangle: &[[f32; 3]; 3]) -> bool {
+    let edge1 = sub(&triangle[1], &triangle[0]);
+    let edge2 = sub(&triangle[2], &triangle[0]);
+    let p = cross(direction, &edge2);
+    let determinant = dot(&edge1, &p);
+    if determinant.abs() < 1e-8 {
+        return false;
+    }
+    let t = sub(origin, &triangle[0]);
+    let u = dot(&t, &p) / determinant;
+    if !(0.0..=1.0).contains(&u) {
+        return false;
+    }
+    let q = cross(&t, &edge1);
+    let v = dot(direction, &q) / determinant;
+    if v < 0.0 || u + v > 1.0 {
+        return false;
+    }
+    dot(&edge2, &q) / determinant > 1e-6
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Triangles of a grid of small quads at several heights.
+    fn grid() -> Vec<[[f32; 3]; 3]> {
+        let mut triangles = Vec::new();
+        for x in 0..6 {
+            for y in 0..6 {
+                let (x, y, z) = (x as f32, y as f32, ((x * 7 + y * 3) % 5) as f32);
+                triangles.push([[x, y, z], [x + 0.8, y, z], [x, y + 0.8, z]]);
+                triangles.push([[x + 0.8, y, z], [x + 0.8, y + 0.8, z], [x, y + 0.8, z]]);
+            }
+        }
+        triangles
+    }
+
+    #[test]
+    fn bvh_matches_brute_force() {
+        let triangles = grid();
+        let bvh = Bvh::new(triangles.clone());
+        for origin in [[2.5, 2.5, -1.0], [0.4, 4.3, 2.5], [6.5, -0.5, 1.0]] {
+            for direction in hemisphere(normalize([0.2, -0.1, 1.0]), 64) {
+                let expected = triangles
+                    .iter()
+                    .any(|triangle| intersects(&origin, &direction, triangle));
+                assert_eq!(bvh.occludes(&origin, &direction), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn empty_bvh_occludes_nothing() {
+        assert!(!Bvh::new(Vec::new()).occludes(&[0.0; 3], &[0.0, 0.0, 1.0]));
+    }
+}
@@ -0,0 +1,118 @@
+use super::{Object, ObjectFlags, Packing, SPrimMesh, SPrimObject, SPrimObjectHeader};
+use crate::log;
+use crate::profile::Profile;
+use crate::GameVersion;
+
+impl SPrimObjectHeader {
+    /// Applies the conversion decisions of the profile before writing in the output game version.
+    pub(super) fn convert(
+        &mut self,
+        output_version: &GameVersion,
+        profile: &Profile,
+    ) -> log::Result<()> {
+        if let GameVersion::Hma | GameVersion::Alpha = output_version {
+            self.check_single_uv_channel();
+        }
+        if profile.remove_cloth {
+            let num_objects = self.objects.len();
+            self.objects.retain(|object| match object {
+                Object::Weighted(object) => {
+                    object.mesh.sub_mesh.as_ref().unwrap().offset_cloth > 0
+                        || object.mesh.cloth_id == 0
+                }
+                _ => true,
+            });
+            if self.objects.len() < num_objects {
+                log!("Removed {} cloth meshes", num_objects - self.objects.len());
+            }
+            self.num_objects = self.objects.len() as u32;
+        }
+        let ps3_edge = self.objects.iter().position(|object| {
+            let mesh = object.mesh();
+            mesh.object.has_flag(ObjectFlags::Ps3Edge)
+                || mesh
+                    .sub_mesh
+                    .as_ref()
+                    .unwrap()
+                    .object
+                    .has_flag(ObjectFlags::Ps3Edge)
+        });
+        if let Some(index) = ps3_edge {
+            return Err(log::error(format!(
+                "Error: Object {} uses PS3 Edge compressed geometry, which cannot be decoded\nEdge geometry is only found in console builds and cannot be written by PC versions",
+                index
+            )));
+        }
+        let header_flags = self.header_flags;
+        let is_weighted = self.is_weighted();
+        for object in &mut self.objects {
+            let mesh = object.mesh_mut();
+            mesh.object.header.draw_destination = get_draw_destination(
+                header_flags,
+                mesh.object.header.draw_destination,
+                output_version,
+                &profile.packing,
+            );
+            mesh.convert(is_weighted, output_version, profile);
+        }
+        Ok(())
+    }
+
+    fn check_single_uv_channel(&mut self) {
+        let dropped = self
+            .objects
+            .iter_mut()
+            .map(|object| object.mesh_mut())
+            .map(|mesh| mesh.drop_extra_uv_channels())
+            .filter(|dropped| *dropped)
+            .count();
+        if dropped > 0 {
+            log!(
+                "Warning: Output game version only supports one UV channel, dropped extra UV channels from {} sub-meshes",
+                dropped
+            );
+        }
+    }
+}
+
+impl SPrimMesh {
+    fn convert(&mut self, is_weighted: bool, output_version: &GameVersion, profile: &Profile) {
+        convert_color1(&mut self.object, output_version, profile);
+        let sub_mesh = self.sub_mesh.as_mut().unwrap();
+        convert_color1(&mut sub_mesh.object, output_version, profile);
+        if !profile.keep_num_indices_extra || sub_mesh.num_indices_extra.is_none() {
+            sub_mesh.num_indices_extra = Some(profile.default_num_indices_extra);
+        }
+        sub_mesh.num_uv_channels = match sub_mesh.uv_channels() {
+            1 if is_weighted => profile.weighted_num_uv_channels,
+            1 => profile.static_num_uv_channels,
+            channels => channels as u32,
+        };
+    }
+}
+
+fn convert_color1(object: &mut SPrimObject, output_version: &GameVersion, profile: &Profile) {
+    if let GameVersion::Hm2016 | GameVersion::Woa = output_version {
+        object.color1.get_or_insert(profile.default_color1);
+    }
+}
+
+fn get_draw_destination(
+    header_flags: u32,
+    draw_destination: u8,
+    output_version: &GameVersion,
+    packing: &Packing,
+) -> u8 {
+    match (packing, output_version) {
+        (Packing::Separate, _) => draw_destination & 0xF,
+        (Packing::Interleaved, _) => draw_destination & 0xF | 0x80,
+        (Packing::Auto, GameVersion::Alpha) => {
+            if header_flags & 8 == 8 {
+                draw_destination & 0xF
+            } else {
+                0x81
+            }
+        }
+        (Packing::Auto, _) => draw_destination & 0xF,
+    }
+}
@@ -0,0 +1,44 @@
+use super::Prim;
+use crate::GameVersion;
+use byteorder::{ByteOrder, LittleEndian};
+
+/// Size of SPrimObject before HM2016 added color1.
+const LEGACY_OBJECT_SIZE: usize = 40;
+/// Size of SPrimObject in HM2016 and WOA.
+const OBJECT_SIZE: usize = 44;
+
+impl Prim {
+    /// Guesses the layout of a PRIM from the offsets its objects store after SPrimObject, which
+    /// only point inside the file when read with the right object size. HM2016 and WOA share a
+    /// layout, so this returns ALPHA or WOA, or None when neither or both layouts fit.
+    pub fn detect_version(buffer: &[u8]) -> Option<GameVersion> {
+        let read_u32 = |offset: usize| -> Option<usize> {
+            let bytes = buffer.get(offset..offset.checked_add(4)?)?;
+            Some(LittleEndian::read_u32(bytes) as usize)
+        };
+        let main_offset = read_u32(0)?;
+        let num_objects = read_u32(main_offset + 12)?;
+        let object_table = read_u32(main_offset + 16)?;
+        let objects = (0..num_objects)
+            .map(|i| read_u32(object_table + i * 4))
+            .collect::<Option<Vec<_>>>()?;
+        let fits = |object_size: usize| {
+            objects.iter().all(|&object| {
+                // Meshes point to a sub-mesh table, sub-meshes store num_vertices and
+                // offset_vertices after their own SPrimObject.
+                let vertices = read_u32(object + object_size)
+                    .filter(|table| *table > 0)
+                    .and_then(read_u32)
+                    .filter(|sub_mesh| *sub_mesh > 0)
+                    .map(|sub_mesh| sub_mesh + object_size);
+                let offset_vertices = vertices.and_then(|vertices| read_u32(vertices + 4));
+                matches!(offset_vertices, Some(offset) if offset < buffer.len())
+            })
+        };
+        match (fits(LEGACY_OBJECT_SIZE), fits(OBJECT_SIZE)) {
+            (true, false) => Some(GameVersion::Alpha),
+            (false, true) => Some(GameVersion::Woa),
+            _ => None,
+        }
+    }
+}
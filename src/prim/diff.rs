@@ -0,0 +1,420 @@
+use super::info::{flag_names, HEADER_FLAG_NAMES, OBJECT_FLAG_NAMES};
+use super::{Object, Prim, SPrimMesh, SPrimObject, SPrimSubMesh, Vertices};
+use std::fmt::Debug;
+
+/// Number of offending vertex or index positions listed per stream.
+const MAX_LISTED: usize = 5;
+
+struct Report {
+    tolerance: f32,
+    differences: usize,
+}
+
+impl Report {
+    fn field<T: PartialEq + Debug>(&mut self, path: &str, name: &str, a: T, b: T) {
+        if a != b {
+            log!("{}: {}: {:?} vs {:?}", path, name, a, b);
+            self.differences += 1;
+        }
+    }
+
+    fn hex_field(&mut self, path: &str, name: &str, a: u32, b: u32) {
+        if a != b {
+            log!("{}: {}: {:#x} vs {:#x}", path, name, a, b);
+            self.differences += 1;
+        }
+    }
+
+    fn flags(&mut self, path: &str, name: &str, a: u32, b: u32, names: &[(u32, &str)]) {
+        if a != b {
+            log!(
+                "{}: {}: {} vs {}",
+                path,
+                name,
+                flag_names(a, names),
+                flag_names(b, names)
+            );
+            self.differences += 1;
+        }
+    }
+
+    fn block<T: PartialEq>(&mut self, path: &str, name: &str, a: Option<&T>, b: Option<&T>) {
+        match (a, b) {
+            (Some(a), Some(b)) if a != b => log!("{}: {} blocks differ", path, name),
+            (Some(_), None) => log!("{}: {} block only in a", path, name),
+            (None, Some(_)) => log!("{}: {} block only in b", path, name),
+            _ => return,
+        }
+        self.differences += 1;
+    }
+
+    /// Compares two short lists of floats, such as bounding boxes, within the tolerance.
+    fn values(&mut self, path: &str, name: &str, a: &[f32], b: &[f32]) {
+        let differs = a.len() != b.len()
+            || a.iter()
+                .zip(b)
+                .any(|(a, b)| (a - b).abs() > self.tolerance || a.is_nan() != b.is_nan());
+        if differs {
+            log!("{}: {}: {:?} vs {:?}", path, name, a, b);
+            self.differences += 1;
+        }
+    }
+
+    /// Compares two vertex streams of `width` floats per vertex within the tolerance.
+    fn stream(&mut self, path: &str, name: &str, a: &[f32], b: &[f32], width: usize) {
+        let (count_a, count_b) = (a.len() / width, b.len() / width);
+        if count_a != count_b {
+            log!(
+                "{}: {}: {} vertices vs {} vertices",
+                path,
+                name,
+                count_a,
+                count_b
+            );
+            self.differences += 1;
+        }
+        let mut offending = Vec::new();
+        let mut max_delta = 0.0_f32;
+        for (i, (a, b)) in a.chunks_exact(width).zip(b.chunks_exact(width)).enumerate() {
+            let delta = a
+                .iter()
+                .zip(b)
+                .map(|(a, b)| (a - b).abs())
+                .fold(0.0, f32::max);
+            if delta > self.tolerance || delta.is_nan() {
+                offending.push(i);
+                max_delta = max_delta.max(delta);
+            }
+        }
+        if !offending.is_empty() {
+            log!(
+                "{}: {}: {} of {} vertices differ, max delta {}, first vertices: {}",
+                path,
+                name,
+                offending.len(),
+                count_a.min(count_b),
+                max_delta,
+                listed(&offending)
+            );
+            self.differences += 1;
+        }
+    }
+
+    /// Compares two streams element by element for exact equality.
+    fn exact<T: PartialEq>(&mut self, path: &str, name: &str, unit: &str, a: &[T], b: &[T]) {
+        if a.len() != b.len() {
+            log!(
+                "{}: {}: {} {} vs {} {}",
+                path,
+                name,
+                a.len(),
+                unit,
+                b.len(),
+                unit
+            );
+            self.differences += 1;
+        }
+        let offending: Vec<usize> = a
+            .iter()
+            .zip(b)
+            .enumerate()
+            .filter(|(_, (a, b))| a != b)
+            .map(|(i, _)| i)
+            .collect();
+        if !offending.is_empty() {
+            log!(
+                "{}: {}: {} of {} {} differ, first {}: {}",
+                path,
+                name,
+                offending.len(),
+                a.len().min(b.len()),
+                unit,
+                unit,
+                listed(&offending)
+            );
+            self.differences += 1;
+        }
+    }
+}
+
+fn listed(offending: &[usize]) -> String {
+    let mut list: Vec<String> = offending
+        .iter()
+        .take(MAX_LISTED)
+        .map(|i| i.to_string())
+        .collect();
+    if offending.len() > MAX_LISTED {
+        list.push(String::from("..."));
+    }
+    list.join(", ")
+}
+
+fn flatten<const N: usize>(stream: &[[f32; N]]) -> Vec<f32> {
+    stream.iter().flatten().copied().collect()
+}
+
+fn object_key(object: &Object) -> (&'static str, u16, u8, u8) {
+    let kind = match object {
+        Object::Mesh(_) => "static",
+        Object::Weighted(_) => "weighted",
+    };
+    let object = object.object();
+    (kind, object.material_id, object.lod_mask, object.variant_id)
+}
+
+/// Pairs objects at the same index when they look alike, otherwise the first unpaired object
+/// of b with the same kind, material, LOD mask and variant.
+fn align(a: &[Object], b: &[Object]) -> Vec<(Option<usize>, Option<usize>)> {
+    let mut used = vec![false; b.len()];
+    let mut pairs = Vec::new();
+    for (i, object) in a.iter().enumerate() {
+        let key = object_key(object);
+        let matched = if i < b.len() && !used[i] && object_key(&b[i]) == key {
+            Some(i)
+        } else {
+            (0..b.len()).find(|j| !used[*j] && object_key(&b[*j]) == key)
+        };
+        if let Some(j) = matched {
+            used[j] = true;
+        }
+        pairs.push((Some(i), matched));
+    }
+    pairs.extend((0..b.len()).filter(|j| !used[*j]).map(|j| (None, Some(j))));
+    pairs
+}
+
+impl Prim {
+    /// Prints the differences to another PRIM and returns how many were found.
+    pub fn diff(&self, other: &Prim, tolerance: f32) -> usize {
+        let mut report = Report {
+            tolerance,
+            differences: 0,
+        };
+        let (a, b) = (&self.header, &other.header);
+        let path = "header";
+        report.field(path, "kind", a.kind(), b.kind());
+        report.hex_field(
+            path,
+            "draw_destination",
+            a.header.draw_destination as u32,
+            b.header.draw_destination as u32,
+        );
+        report.hex_field(
+            path,
+            "pack_type",
+            a.header.pack_type as u32,
+            b.header.pack_type as u32,
+        );
+        report.flags(
+            path,
+            "header_flags",
+            a.header_flags,
+            b.header_flags,
+            &HEADER_FLAG_NAMES,
+        );
+        report.hex_field(
+            path,
+            "bone_rig_resource_index",
+            a.bone_rig_resource_index,
+            b.bone_rig_resource_index,
+        );
+        report.field(path, "num_objects", a.objects.len(), b.objects.len());
+        report.values(
+            path,
+            "bounding_box",
+            &[a.bounding_box_min, a.bounding_box_max].concat(),
+            &[b.bounding_box_min, b.bounding_box_max].concat(),
+        );
+        for pair in align(&a.objects, &b.objects) {
+            match pair {
+                (Some(i), Some(j)) => {
+                    let path = if i == j {
+                        format!("object {}", i)
+                    } else {
+                        format!("object {} / {}", i, j)
+                    };
+                    diff_object(&mut report, &path, &a.objects[i], &b.objects[j]);
+                }
+                (Some(i), None) => {
+                    log!("object {}: only in a", i);
+                    report.differences += 1;
+                }
+                (None, Some(j)) => {
+                    log!("object {}: only in b", j);
+                    report.differences += 1;
+                }
+                (None, None) => (),
+            }
+        }
+        report.differences
+    }
+}
+
+fn diff_object(report: &mut Report, path: &str, a: &Object, b: &Object) {
+    diff_sprim_object(report, path, a.object(), b.object());
+    match (a, b) {
+        (Object::Weighted(a), Object::Weighted(b)) => {
+            diff_mesh(report, path, &a.mesh, &b.mesh);
+            report.field(path, "num_copy_bones", a.num_copy_bones, b.num_copy_bones);
+            report.block(
+                path,
+                "copy bones",
+                a.copy_bones.as_ref(),
+                b.copy_bones.as_ref(),
+            );
+            report.block(
+                path,
+                "bone indices",
+                a.bone_indicies.as_ref(),
+                b.bone_indicies.as_ref(),
+            );
+            report.block(
+                path,
+                "bone info",
+                a.bone_info.as_ref(),
+                b.bone_info.as_ref(),
+            );
+        }
+        (Object::Mesh(a), Object::Mesh(b)) => diff_mesh(report, path, a, b),
+        _ => (),
+    }
+}
+
+fn diff_sprim_object(report: &mut Report, path: &str, a: &SPrimObject, b: &SPrimObject) {
+    report.hex_field(
+        path,
+        "draw_destination",
+        a.header.draw_destination as u32,
+        b.header.draw_destination as u32,
+    );
+    report.hex_field(
+        path,
+        "prim_type",
+        a.header.prim_type as u32,
+        b.header.prim_type as u32,
+    );
+    report.field(path, "sub_type", a.sub_type, b.sub_type);
+    report.flags(
+        path,
+        "flags",
+        a.flags as u32,
+        b.flags as u32,
+        &OBJECT_FLAG_NAMES,
+    );
+    report.hex_field(path, "lod_mask", a.lod_mask as u32, b.lod_mask as u32);
+    report.field(path, "variant_id", a.variant_id, b.variant_id);
+    report.field(path, "bias", a.bias, b.bias);
+    report.field(path, "offset", a.offset, b.offset);
+    report.field(path, "material_id", a.material_id, b.material_id);
+    report.hex_field(path, "wire_color", a.wire_color, b.wire_color);
+    report.field(path, "color1", a.color1, b.color1);
+    report.values(
+        path,
+        "bounding_box",
+        &[a.bounding_box_min, a.bounding_box_max].concat(),
+        &[b.bounding_box_min, b.bounding_box_max].concat(),
+    );
+}
+
+fn diff_mesh(report: &mut Report, path: &str, a: &SPrimMesh, b: &SPrimMesh) {
+    report.values(
+        path,
+        "position_scale/bias",
+        &[a.position_scale, a.position_bias].concat(),
+        &[b.position_scale, b.position_bias].concat(),
+    );
+    report.values(
+        path,
+        "uv_scale/bias",
+        &[a.uv_scale, a.uv_bias].concat(),
+        &[b.uv_scale, b.uv_bias].concat(),
+    );
+    report.hex_field(path, "cloth_id", a.cloth_id as u32, b.cloth_id as u32);
+    let path = format!("{} sub-mesh", path);
+    let (a, b) = (a.sub_mesh.as_ref().unwrap(), b.sub_mesh.as_ref().unwrap());
+    diff_sprim_object(report, &path, &a.object, &b.object);
+    diff_sub_mesh(report, &path, a, b);
+}
+
+fn diff_sub_mesh(report: &mut Report, path: &str, a: &SPrimSubMesh, b: &SPrimSubMesh) {
+    report.field(path, "num_vertices", a.num_vertices, b.num_vertices);
+    report.field(path, "num_indices", a.num_indices, b.num_indices);
+    report.field(
+        path,
+        "num_indices_extra",
+        a.num_indices_extra,
+        b.num_indices_extra,
+    );
+    report.field(
+        path,
+        "num_uv_channels",
+        a.num_uv_channels,
+        b.num_uv_channels,
+    );
+    if let (Some(a), Some(b)) = (&a.vertices, &b.vertices) {
+        diff_vertices(report, path, a, b);
+    }
+    if let (Some(a), Some(b)) = (&a.indices, &b.indices) {
+        report.exact(path, "index buffer", "indices", &a.indices, &b.indices);
+    }
+    report.block(
+        path,
+        "collision",
+        a.collision.as_ref(),
+        b.collision.as_ref(),
+    );
+    report.block(path, "cloth", a.cloth.as_ref(), b.cloth.as_ref());
+}
+
+fn diff_vertices(report: &mut Report, path: &str, a: &Vertices, b: &Vertices) {
+    report.stream(
+        path,
+        "positions",
+        &flatten(&a.positions),
+        &flatten(&b.positions),
+        4,
+    );
+    let weights = |vertices: &Vertices| -> Vec<f32> {
+        vertices
+            .weights
+            .iter()
+            .flat_map(|(weights, extra)| weights.iter().chain(extra).copied())
+            .collect()
+    };
+    report.stream(path, "weights", &weights(a), &weights(b), 6);
+    report.exact(path, "bones", "vertices", &a.bones, &b.bones);
+    report.stream(
+        path,
+        "normals",
+        &flatten(&a.normals),
+        &flatten(&b.normals),
+        4,
+    );
+    report.stream(
+        path,
+        "tangents",
+        &flatten(&a.tangents),
+        &flatten(&b.tangents),
+        4,
+    );
+    report.stream(
+        path,
+        "bitangents",
+        &flatten(&a.bitangents),
+        &flatten(&b.bitangents),
+        4,
+    );
+    report.field(path, "uv channels", a.uvs.len(), b.uvs.len());
+    for (channel, (a, b)) in a.uvs.iter().zip(&b.uvs).enumerate() {
+        report.stream(path, &format!("uv{}", channel), &flatten(a), &flatten(b), 2);
+    }
+    let colors = |vertices: &Vertices| -> Vec<f32> {
+        vertices
+            .colors
+            .iter()
+            .flatten()
+            .map(|x| *x as f32 / 255.0)
+            .collect()
+    };
+    report.stream(path, "colors", &colors(a), &colors(b), 4);
+}
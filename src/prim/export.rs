@@ -0,0 +1,57 @@
+use super::Prim;
+use crate::log;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+impl Prim {
+    /// Exports the meshes as a Wavefront OBJ file, one OBJ object per PRIM object.
+    pub fn export_obj(&self, path: &Path) -> log::Result<()> {
+        let file = match File::create(path) {
+            Ok(file) => file,
+            Err(err) => {
+                return Err(log::error(format!(
+                    "Error creating file {}: {}",
+                    path.to_str().unwrap(),
+                    err
+                ))
+                .in_file(path));
+            }
+        };
+        let mut writer = BufWriter::new(file);
+        let mut position_offset = 1;
+        let mut uv_offset = 1;
+        for (i, object) in self.header.objects.iter().enumerate() {
+            let mesh = object.mesh();
+            let sub_mesh = mesh.sub_mesh.as_ref().unwrap();
+            let (Some(vertices), Some(indices)) = (&sub_mesh.vertices, &sub_mesh.indices) else {
+                continue;
+            };
+            writeln!(writer, "o object_{}", i).unwrap();
+            writeln!(writer, "# material_id: {}", mesh.object.material_id).unwrap();
+            for position in &vertices.positions {
+                writeln!(writer, "v {} {} {}", position[0], position[1], position[2]).unwrap();
+            }
+            for normal in &vertices.normals {
+                writeln!(writer, "vn {} {} {}", normal[0], normal[1], normal[2]).unwrap();
+            }
+            for uv in &vertices.uvs[0] {
+                writeln!(writer, "vt {} {}", uv[0], 1.0 - uv[1]).unwrap();
+            }
+            for triangle in indices.indices[..sub_mesh.num_indices as usize].chunks_exact(3) {
+                let face: Vec<String> = triangle
+                    .iter()
+                    .map(|index| {
+                        let position = *index as usize + position_offset;
+                        let uv = *index as usize + uv_offset;
+                        format!("{}/{}/{}", position, uv, uv)
+                    })
+                    .collect();
+                writeln!(writer, "f {}", face.join(" ")).unwrap();
+            }
+            position_offset += vertices.positions.len();
+            uv_offset += vertices.positions.len();
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,160 @@
+use super::{Prim, SPrimMesh, SPrimObject};
+
+pub(super) const HEADER_FLAG_NAMES: [(u32, &str); 6] = [
+    (0x1, "HasBones"),
+    (0x2, "HasFrames"),
+    (0x4, "IsLinkedObject"),
+    (0x8, "IsWeightedObject"),
+    (0x100, "UseBounds"),
+    (0x200, "HasHiResPositions"),
+];
+
+pub(super) const OBJECT_FLAG_NAMES: [(u32, &str); 7] = [
+    (0x1, "XAxisLocked"),
+    (0x2, "YAxisLocked"),
+    (0x4, "ZAxisLocked"),
+    (0x8, "HiResPositions"),
+    (0x10, "Ps3Edge"),
+    (0x20, "Color1"),
+    (0x40, "IsNoPhysicsProp"),
+];
+
+pub(super) const SUB_TYPE_NAMES: [&str; 7] = [
+    "Standard",
+    "Linked",
+    "Weighted",
+    "StandardUv2",
+    "StandardUv3",
+    "StandardUv4",
+    "SpeedTree",
+];
+
+pub(super) fn flag_names(flags: u32, names: &[(u32, &str)]) -> String {
+    let names: Vec<&str> = names
+        .iter()
+        .filter(|(flag, _)| flags & flag != 0)
+        .map(|(_, name)| *name)
+        .collect();
+    if names.is_empty() {
+        format!("{:#x}", flags)
+    } else {
+        format!("{:#x} ({})", flags, names.join(" | "))
+    }
+}
+
+impl Prim {
+    /// Material hashes in material_id order: the references of the resource, without the bone rig.
+    pub(super) fn materials(&self, references: &[(u64, u8)]) -> Vec<u64> {
+        let header = &self.header;
+        let bone_rig = if header.is_weighted() || header.is_linked() {
+            Some(header.bone_rig_resource_index as usize)
+        } else {
+            None
+        };
+        references
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| Some(*i) != bone_rig)
+            .map(|(_, (hash, _))| *hash)
+            .collect()
+    }
+
+    /// Prints the summary, resolving the bone rig and materials against the reference table of
+    /// the resource when it is known.
+    pub fn print_info(&self, references: &[(u64, u8)]) {
+        let header = &self.header;
+        log!("PRIM: {} object header", header.kind());
+        log!("  draw_destination: {:#x}", header.header.draw_destination);
+        log!("  pack_type: {:#x}", header.header.pack_type);
+        log!(
+            "  header_flags: {}",
+            flag_names(header.header_flags, &HEADER_FLAG_NAMES)
+        );
+        log!(
+            "  bone_rig_resource_index: {:#x}",
+            header.bone_rig_resource_index
+        );
+        if header.is_weighted() || header.is_linked() {
+            if let Some((hash, _)) = references.get(header.bone_rig_resource_index as usize) {
+                log!("  bone rig: {:016X}", hash);
+            }
+        }
+        if !references.is_empty() {
+            log!("  references:");
+            for (hash, flags) in references {
+                log!("    {:016X} (flags {:#04x})", hash, flags);
+            }
+        }
+        let materials = self.materials(references);
+        log!("  num_objects: {}", header.objects.len());
+        log!(
+            "  bounding_box: {:?} - {:?}",
+            header.bounding_box_min,
+            header.bounding_box_max
+        );
+        let material_ids: Vec<String> = self
+            .material_ids()
+            .iter()
+            .map(|id| id.to_string())
+            .collect();
+        log!("  material_ids in use: {}", material_ids.join(", "));
+        for (i, object) in header.objects.iter().enumerate() {
+            log!("Object {}:", i);
+            print_mesh_info(object.mesh());
+            if !references.is_empty() {
+                match materials.get(object.object().material_id as usize) {
+                    Some(hash) => log!("  material: {:016X}", hash),
+                    None => log!("  material: not in the reference table"),
+                }
+            }
+        }
+    }
+}
+
+fn print_object_info(object: &SPrimObject) {
+    log!(
+        "  sub_type: {}",
+        SUB_TYPE_NAMES
+            .get(object.sub_type as usize)
+            .unwrap_or(&"Unknown")
+    );
+    log!(
+        "  flags: {}",
+        flag_names(object.flags as u32, &OBJECT_FLAG_NAMES)
+    );
+    log!("  lod_mask: {:#x}", object.lod_mask);
+    log!("  variant_id: {}", object.variant_id);
+    log!("  material_id: {}", object.material_id);
+}
+
+fn print_mesh_info(mesh: &SPrimMesh) {
+    let object = &mesh.object;
+    let sub_mesh = mesh.sub_mesh.as_ref().unwrap();
+    print_object_info(object);
+    if let Some(color1) = sub_mesh.object.color1 {
+        log!("  color1: {:#x}", color1);
+    }
+    log!(
+        "  vertices: {}, triangles: {}, uv channels: {}",
+        sub_mesh.num_vertices,
+        sub_mesh.num_indices / 3,
+        sub_mesh.uv_channels()
+    );
+    log!(
+        "  packing: {} (draw_destination {:#x})",
+        if object.header.draw_destination & 0x80 == 0x80 {
+            "interleaved"
+        } else {
+            "separate"
+        },
+        object.header.draw_destination
+    );
+    log!(
+        "  bounding_box: {:?} - {:?}",
+        object.bounding_box_min,
+        object.bounding_box_max
+    );
+    if mesh.cloth_id != 0 || sub_mesh.cloth.is_some() {
+        log!("  cloth_id: {:#x}", mesh.cloth_id);
+    }
+}
@@ -0,0 +1,301 @@
+use super::info::{flag_names, HEADER_FLAG_NAMES, OBJECT_FLAG_NAMES, SUB_TYPE_NAMES};
+use super::{
+    Collision, Indices, Object, ObjectFlags, Prim, SPrimMesh, SPrimObject, SPrimSubMesh, Vertices,
+};
+use eframe::egui;
+use egui::{CollapsingHeader, DragValue, Grid, Ui};
+use std::fmt::Display;
+
+/// Rows of vertex, index and bone arrays shown before the rest is elided.
+const MAX_ROWS: usize = 64;
+
+/// Object flags that change how the vertex streams are encoded, so editing them would corrupt
+/// the written PRIM. PS3 Edge geometry cannot be read back at all.
+const LAYOUT_FLAGS: u8 = ObjectFlags::XAxisLocked as u8
+    | ObjectFlags::YAxisLocked as u8
+    | ObjectFlags::ZAxisLocked as u8
+    | ObjectFlags::HiResPositions as u8
+    | ObjectFlags::Ps3Edge as u8
+    | ObjectFlags::Color1 as u8;
+
+impl Prim {
+    /// Shows the PRIM as a tree of header, objects, sub-meshes and their data blocks, with the
+    /// safe object fields editable in place. Returns whether a field was edited.
+    pub fn inspect(&mut self, ui: &mut Ui) -> bool {
+        let header = &mut self.header;
+        let mut changed = false;
+        CollapsingHeader::new(format!("{} object header", header.kind()))
+            .id_source("header")
+            .default_open(true)
+            .show(ui, |ui| {
+                fields(ui, "header", |ui| {
+                    row(
+                        ui,
+                        "draw_destination",
+                        format!("{:#x}", header.header.draw_destination),
+                    );
+                    row(ui, "pack_type", format!("{:#x}", header.header.pack_type));
+                    row(
+                        ui,
+                        "header_flags",
+                        flag_names(header.header_flags, &HEADER_FLAG_NAMES),
+                    );
+                    row(
+                        ui,
+                        "bone_rig_resource_index",
+                        header.bone_rig_resource_index,
+                    );
+                    row(ui, "num_objects", header.num_objects);
+                    row(
+                        ui,
+                        "bounding_box_min",
+                        format!("{:?}", header.bounding_box_min),
+                    );
+                    row(
+                        ui,
+                        "bounding_box_max",
+                        format!("{:?}", header.bounding_box_max),
+                    );
+                });
+                for (i, object) in header.objects.iter_mut().enumerate() {
+                    changed |= inspect_object(ui, i, object);
+                }
+            });
+        changed
+    }
+}
+
+fn inspect_object(ui: &mut Ui, index: usize, object: &mut Object) -> bool {
+    let title = match object {
+        Object::Mesh(_) => format!("Object {}: mesh", index),
+        Object::Weighted(_) => format!("Object {}: weighted mesh", index),
+    };
+    let mut changed = false;
+    CollapsingHeader::new(title)
+        .id_source(("object", index))
+        .show(ui, |ui| match object {
+            Object::Mesh(mesh) => changed = inspect_mesh(ui, mesh),
+            Object::Weighted(weighted) => {
+                changed = inspect_mesh(ui, &mut weighted.mesh);
+                CollapsingHeader::new("Bones").show(ui, |ui| {
+                    fields(ui, "bones", |ui| {
+                        row(ui, "num_copy_bones", weighted.num_copy_bones);
+                        if let Some(bone_info) = &weighted.bone_info {
+                            row(ui, "bone_info", format!("{} bytes", bone_info.data.len()));
+                        }
+                    });
+                    if let Some(copy_bones) = &weighted.copy_bones {
+                        CollapsingHeader::new("copy_bones").show(ui, |ui| {
+                            rows(ui, copy_bones.data.chunks_exact(2), |ui, i, pair| {
+                                row(ui, i, format!("{} -> {}", pair[0], pair[1]));
+                            });
+                        });
+                    }
+                    if let Some(bone_indices) = &weighted.bone_indicies {
+                        CollapsingHeader::new("bone_indices").show(ui, |ui| {
+                            rows(ui, bone_indices.data.iter(), row);
+                        });
+                    }
+                });
+            }
+        });
+    changed
+}
+
+fn inspect_mesh(ui: &mut Ui, mesh: &mut SPrimMesh) -> bool {
+    let mut changed = inspect_sprim_object(ui, "object", &mut mesh.object);
+    fields(ui, "mesh", |ui| {
+        row(ui, "position_scale", format!("{:?}", mesh.position_scale));
+        row(ui, "position_bias", format!("{:?}", mesh.position_bias));
+        row(ui, "uv_scale", format!("{:?}", mesh.uv_scale));
+        row(ui, "uv_bias", format!("{:?}", mesh.uv_bias));
+        row(ui, "cloth_id", format!("{:#x}", mesh.cloth_id));
+    });
+    if let Some(sub_mesh) = &mut mesh.sub_mesh {
+        CollapsingHeader::new("Sub-mesh").show(ui, |ui| {
+            changed |= inspect_sub_mesh(ui, sub_mesh);
+        });
+    }
+    changed
+}
+
+fn inspect_sub_mesh(ui: &mut Ui, sub_mesh: &mut SPrimSubMesh) -> bool {
+    let changed = inspect_sprim_object(ui, "sub_mesh_object", &mut sub_mesh.object);
+    fields(ui, "sub_mesh", |ui| {
+        row(ui, "num_vertices", sub_mesh.num_vertices);
+        row(ui, "num_indices", sub_mesh.num_indices);
+        if let Some(num_indices_extra) = sub_mesh.num_indices_extra {
+            row(ui, "num_indices_extra", num_indices_extra);
+        }
+        row(ui, "num_uv_channels", sub_mesh.num_uv_channels);
+    });
+    if let Some(vertices) = &sub_mesh.vertices {
+        CollapsingHeader::new(format!("Vertices ({})", vertices.positions.len()))
+            .show(ui, |ui| inspect_vertices(ui, vertices));
+    }
+    if let Some(indices) = &sub_mesh.indices {
+        CollapsingHeader::new(format!("Indices ({})", indices.indices.len()))
+            .show(ui, |ui| inspect_indices(ui, indices));
+    }
+    if let Some(collision) = &sub_mesh.collision {
+        CollapsingHeader::new(format!(
+            "Collision ({} boxes)",
+            collision.bounding_boxes.len()
+        ))
+        .show(ui, |ui| inspect_collision(ui, collision));
+    }
+    if let Some(cloth) = &sub_mesh.cloth {
+        CollapsingHeader::new(format!("Cloth ({} bytes)", cloth.data.len())).show(ui, |ui| {
+            rows(ui, cloth.data.chunks(16), |ui, i, bytes| {
+                let hex: Vec<String> = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+                row(ui, format!("{:#06x}", i * 16), hex.join(" "));
+            });
+        });
+    }
+    changed
+}
+
+fn inspect_sprim_object(ui: &mut Ui, id: &str, object: &mut SPrimObject) -> bool {
+    let mut changed = false;
+    fields(ui, id, |ui| {
+        row(
+            ui,
+            "sub_type",
+            SUB_TYPE_NAMES
+                .get(object.sub_type as usize)
+                .unwrap_or(&"Unknown"),
+        );
+        ui.label("flags");
+        ui.horizontal_wrapped(|ui| {
+            for (flag, name) in OBJECT_FLAG_NAMES {
+                let flag = flag as u8;
+                let mut set = object.flags & flag != 0;
+                let checkbox = ui.add_enabled(
+                    flag & LAYOUT_FLAGS == 0,
+                    egui::Checkbox::new(&mut set, name),
+                );
+                if checkbox
+                    .on_disabled_hover_text("Changes the vertex layout")
+                    .changed()
+                {
+                    object.flags ^= flag;
+                    changed = true;
+                }
+            }
+        });
+        ui.end_row();
+        ui.label("lod_mask");
+        changed |= ui
+            .add(DragValue::new(&mut object.lod_mask).hexadecimal(2, false, false))
+            .changed();
+        ui.end_row();
+        ui.label("variant_id");
+        changed |= ui.add(DragValue::new(&mut object.variant_id)).changed();
+        ui.end_row();
+        row(ui, "bias", object.bias);
+        row(ui, "offset", object.offset);
+        ui.label("material_id");
+        changed |= ui.add(DragValue::new(&mut object.material_id)).changed();
+        ui.end_row();
+        ui.label("wire_color");
+        changed |= color_edit(ui, &mut object.wire_color);
+        ui.end_row();
+        if let Some(color1) = &mut object.color1 {
+            ui.label("color1");
+            changed |= color_edit(ui, color1);
+            ui.end_row();
+        }
+        row(
+            ui,
+            "bounding_box_min",
+            format!("{:?}", object.bounding_box_min),
+        );
+        row(
+            ui,
+            "bounding_box_max",
+            format!("{:?}", object.bounding_box_max),
+        );
+    });
+    changed
+}
+
+fn inspect_vertices(ui: &mut Ui, vertices: &Vertices) {
+    rows(ui, vertices.positions.iter(), |ui, i, position| {
+        let mut value = format!("position {:?}", &position[..3]);
+        if let Some(normal) = vertices.normals.get(i) {
+            value += &format!("  normal {:?}", &normal[..3]);
+        }
+        for uv in vertices.uvs.iter().filter_map(|uvs| uvs.get(i)) {
+            value += &format!("  uv {:?}", uv);
+        }
+        if let Some((weights, bones)) = vertices.weights.get(i).zip(vertices.bones.get(i)) {
+            value += &format!("  weights {:?} {:?}", weights.0, weights.1);
+            value += &format!("  bones {:?} {:?}", bones.0, bones.1);
+        }
+        if let Some(color) = vertices.colors.get(i) {
+            value += &format!("  color {:02x?}", color);
+        }
+        row(ui, i, value);
+    });
+}
+
+fn inspect_indices(ui: &mut Ui, indices: &Indices) {
+    rows(ui, indices.indices.chunks(3), |ui, i, triangle| {
+        row(ui, i, format!("{:?}", triangle));
+    });
+}
+
+fn inspect_collision(ui: &mut Ui, collision: &Collision) {
+    fields(ui, "collision", |ui| {
+        row(ui, "triangles_per_box", collision.triangles_per_box);
+    });
+    rows(
+        ui,
+        collision.bounding_boxes.iter(),
+        |ui, i, bounding_box| {
+            row(
+                ui,
+                i,
+                format!("{:?} - {:?}", bounding_box[0], bounding_box[1]),
+            );
+        },
+    );
+}
+
+/// Edits a u32 color stored as RGBA bytes, like color1.
+fn color_edit(ui: &mut Ui, color: &mut u32) -> bool {
+    let mut rgba = color.to_le_bytes();
+    let changed = ui.color_edit_button_srgba_unmultiplied(&mut rgba).changed();
+    *color = u32::from_le_bytes(rgba);
+    changed
+}
+
+fn fields(ui: &mut Ui, id: &str, add_contents: impl FnOnce(&mut Ui)) {
+    Grid::new(id)
+        .num_columns(2)
+        .striped(true)
+        .show(ui, add_contents);
+}
+
+fn row(ui: &mut Ui, name: impl Display, value: impl Display) {
+    ui.label(name.to_string());
+    ui.label(value.to_string());
+    ui.end_row();
+}
+
+/// A grid of the first MAX_ROWS items, noting how many were left out.
+fn rows<T>(
+    ui: &mut Ui,
+    items: impl ExactSizeIterator<Item = T>,
+    mut add_row: impl FnMut(&mut Ui, usize, T),
+) {
+    let len = items.len();
+    Grid::new("rows").striped(true).show(ui, |ui| {
+        for (i, item) in items.take(MAX_ROWS).enumerate() {
+            add_row(ui, i, item);
+        }
+    });
+    if len > MAX_ROWS {
+        ui.label(format!("… {} more", len - MAX_ROWS));
+    }
+}
@@ -0,0 +1,472 @@
+use super::{GameVersion, Prim};
+use std::cell::RefCell;
+use std::io::Cursor;
+
+/// Kind of structure a byte range of a PRIM file was parsed as.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Block {
+    /// Offset of the main header at the start of the file
+    File,
+    /// SPrimObjectHeader
+    Header,
+    ObjectTable,
+    /// SPrimObject with the fields of its mesh type
+    Object,
+    SubMeshTable,
+    /// SPrimSubMesh
+    SubMesh,
+    Vertices,
+    Indices,
+    Collision,
+    Cloth,
+    CopyBones,
+    BoneIndices,
+    BoneInfo,
+    /// Zeros aligning the next structure to 16 bytes
+    Padding,
+    /// Bytes no structure was parsed from
+    Gap,
+}
+
+impl Block {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Block::File => "File header",
+            Block::Header => "SPrimObjectHeader",
+            Block::ObjectTable => "Object table",
+            Block::Object => "SPrimObject",
+            Block::SubMeshTable => "Sub-mesh table",
+            Block::SubMesh => "SPrimSubMesh",
+            Block::Vertices => "Vertices",
+            Block::Indices => "Indices",
+            Block::Collision => "Collision",
+            Block::Cloth => "Cloth",
+            Block::CopyBones => "Copy bones",
+            Block::BoneIndices => "Bone indices",
+            Block::BoneInfo => "Bone info",
+            Block::Padding => "Padding",
+            Block::Gap => "Unreferenced",
+        }
+    }
+
+    /// Color of the block in the hex views.
+    pub fn color(&self) -> [u8; 3] {
+        match self {
+            Block::File => [230, 230, 230],
+            Block::Header => [255, 210, 90],
+            Block::ObjectTable => [255, 160, 60],
+            Block::Object => [120, 200, 255],
+            Block::SubMeshTable => [170, 140, 255],
+            Block::SubMesh => [90, 150, 255],
+            Block::Vertices => [110, 220, 120],
+            Block::Indices => [200, 230, 90],
+            Block::Collision => [90, 220, 210],
+            Block::Cloth => [255, 130, 200],
+            Block::CopyBones => [220, 170, 120],
+            Block::BoneIndices => [200, 140, 90],
+            Block::BoneInfo => [180, 120, 70],
+            Block::Padding => [110, 110, 110],
+            Block::Gap => [255, 70, 70],
+        }
+    }
+}
+
+/// A byte range of a PRIM file, and the objects it belongs to.
+#[derive(Clone)]
+pub struct Range {
+    pub start: u64,
+    pub end: u64,
+    pub block: Block,
+    /// More than one object when objects share the structure.
+    pub objects: Vec<u32>,
+}
+
+impl Range {
+    pub fn label(&self) -> String {
+        let objects: Vec<String> = self.objects.iter().map(u32::to_string).collect();
+        match objects.len() {
+            0 => self.block.name().to_string(),
+            1 => format!("Object {}: {}", objects[0], self.block.name()),
+            _ => format!("Objects {}: {}", objects.join(", "), self.block.name()),
+        }
+    }
+
+    fn is_same(&self, other: &Range) -> bool {
+        self.start == other.start && self.end == other.end && self.block == other.block
+    }
+}
+
+/// Every byte of a PRIM file mapped to the structure parsed from it, with the problems found.
+#[derive(Default)]
+pub struct Coverage {
+    /// Ranges sorted by offset, including padding and unreferenced gaps.
+    pub layout: Vec<Range>,
+    /// Indices into the layout of ranges that partly overlap.
+    pub overlaps: Vec<(usize, usize)>,
+    /// Indices into the layout of sub-mesh tables and sub-meshes shared between objects.
+    pub shared: Vec<usize>,
+    /// Offset reading stopped at, and the error, when the PRIM could not be read.
+    pub stopped: Option<(u64, String)>,
+}
+
+impl Coverage {
+    /// Where reading stopped, unreferenced data and overlapping structures, one line each.
+    pub fn issues(&self) -> Vec<String> {
+        let mut issues = Vec::new();
+        if let Some((offset, error)) = &self.stopped {
+            issues.push(format!(
+                "Reading stopped at {:#x}, the bytes not read before are unreferenced: {}",
+                offset,
+                error.trim_start_matches("Error: ")
+            ));
+        }
+        for range in &self.layout {
+            if range.block == Block::Gap && self.stopped.is_none() {
+                issues.push(format!(
+                    "{} bytes at {:#x} are not referenced by any structure and are not ported",
+                    range.end - range.start,
+                    range.start
+                ));
+            }
+        }
+        for (a, b) in &self.overlaps {
+            let (a, b) = (&self.layout[*a], &self.layout[*b]);
+            issues.push(format!(
+                "{} at {:#x}-{:#x} overlaps {} at {:#x}-{:#x}",
+                b.label(),
+                b.start,
+                b.end,
+                a.label(),
+                a.start,
+                a.end
+            ));
+        }
+        issues
+    }
+
+    /// Sub-mesh tables and sub-meshes shared between objects, one line each. They are written
+    /// once in the ported PRIM, as long as they stay the same for every object.
+    pub fn shared_structures(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        for index in &self.shared {
+            let range = &self.layout[*index];
+            let objects: Vec<String> = range.objects.iter().map(u32::to_string).collect();
+            let structure = match range.block {
+                Block::SubMeshTable => "sub-mesh table",
+                _ => "sub-mesh",
+            };
+            lines.push(format!(
+                "Objects {} share the {} at {:#x}",
+                objects.join(", "),
+                structure,
+                range.start
+            ));
+        }
+        lines
+    }
+}
+
+#[derive(Default)]
+struct Recorder {
+    ranges: Vec<Range>,
+    object: Option<u32>,
+}
+
+thread_local! {
+    static RECORDER: RefCell<Option<Recorder>> = const { RefCell::new(None) };
+}
+
+/// Starts recording the blocks read on this thread.
+pub(super) fn record() {
+    RECORDER.with(|recorder| *recorder.borrow_mut() = Some(Recorder::default()));
+}
+
+/// Runs a read, recording the bytes it consumed as a block.
+pub(super) fn mark<T>(
+    block: Block,
+    reader: &mut Cursor<Vec<u8>>,
+    read: impl FnOnce(&mut Cursor<Vec<u8>>) -> T,
+) -> T {
+    let start = reader.position();
+    let value = read(reader);
+    let end = reader.position();
+    RECORDER.with(|recorder| {
+        if let Some(recorder) = recorder.borrow_mut().as_mut() {
+            let objects = recorder.object.into_iter().collect();
+            recorder.ranges.push(Range {
+                start,
+                end,
+                block,
+                objects,
+            });
+        }
+    });
+    value
+}
+
+/// Sets the object the blocks read next belong to.
+pub(super) fn set_object(object: Option<u32>) {
+    RECORDER.with(|recorder| {
+        if let Some(recorder) = recorder.borrow_mut().as_mut() {
+            recorder.object = object;
+        }
+    });
+}
+
+/// Stops recording and maps the recorded blocks onto the file. Bytes between the blocks are
+/// padding when they are zeros up to the next 16 byte boundary, and unreferenced otherwise.
+pub(super) fn finish(buffer: &[u8]) -> Coverage {
+    let Some(recorder) = RECORDER.with(|recorder| recorder.borrow_mut().take()) else {
+        return Coverage::default();
+    };
+    let mut ranges = recorder.ranges;
+    ranges.retain(|range| range.end > range.start);
+    ranges.sort_by_key(|range| (range.start, range.end));
+    let mut layout: Vec<Range> = Vec::new();
+    let mut overlaps = Vec::new();
+    // End of the bytes covered so far, and the range reaching it.
+    let mut covered: Option<(u64, usize)> = None;
+    for range in ranges {
+        match layout.last_mut() {
+            // Objects reading the same sub-mesh read the same blocks.
+            Some(last) if last.is_same(&range) => {
+                for object in range.objects {
+                    if !last.objects.contains(&object) {
+                        last.objects.push(object);
+                    }
+                }
+                continue;
+            }
+            // Table entries and the fields weighted meshes add to SPrimMesh are
+            // read one after another.
+            Some(last)
+                if last.block == range.block
+                    && last.objects == range.objects
+                    && last.end == range.start =>
+            {
+                last.end = range.end;
+                if covered.is_none_or(|(end, _)| range.end > end) {
+                    covered = Some((range.end, layout.len() - 1));
+                }
+                continue;
+            }
+            _ => (),
+        }
+        let end = covered.map_or(0, |(end, _)| end);
+        if range.start > end {
+            layout.push(gap(buffer, end, range.start));
+        } else if let Some((end, index)) = covered.filter(|(end, _)| range.start < *end) {
+            overlaps.push((index, layout.len()));
+            if range.end <= end {
+                layout.push(range);
+                continue;
+            }
+        }
+        covered = Some((range.end, layout.len()));
+        layout.push(range);
+    }
+    let end = covered.map_or(0, |(end, _)| end);
+    if (buffer.len() as u64) > end {
+        layout.push(gap(buffer, end, buffer.len() as u64));
+    }
+    // A shared sub-mesh table implies its sub-mesh is shared by the same objects.
+    let is_shared = |range: &Range, block: Block| range.block == block && range.objects.len() > 1;
+    let shared = (0..layout.len())
+        .filter(|&index| {
+            let range = &layout[index];
+            is_shared(range, Block::SubMeshTable)
+                || is_shared(range, Block::SubMesh)
+                    && !layout.iter().any(|table| {
+                        is_shared(table, Block::SubMeshTable) && table.objects == range.objects
+                    })
+        })
+        .collect();
+    Coverage {
+        layout,
+        overlaps,
+        shared,
+        stopped: None,
+    }
+}
+
+impl Prim {
+    /// Reads a PRIM and maps every byte of it to the structure it was parsed as. When reading
+    /// fails, the map holds the structures read up to the error and every other byte is
+    /// unreferenced.
+    pub fn layout(buffer: &[u8], input_version: &GameVersion) -> Coverage {
+        match Prim::read_bytes(buffer.to_vec(), input_version, false) {
+            Ok((_, coverage)) => coverage,
+            Err(err) => {
+                let mut coverage = finish(buffer);
+                for range in &mut coverage.layout {
+                    if range.block == Block::Padding {
+                        range.block = Block::Gap;
+                    }
+                }
+                coverage.stopped = Some((err.offset.unwrap_or(0), err.message));
+                coverage
+            }
+        }
+    }
+}
+
+fn gap(buffer: &[u8], start: u64, end: u64) -> Range {
+    let is_zero = buffer[start as usize..end as usize]
+        .iter()
+        .all(|&byte| byte == 0);
+    let is_alignment = end - start < 0x10 && end.is_multiple_of(0x10);
+    Range {
+        start,
+        end,
+        block: if is_zero && is_alignment {
+            Block::Padding
+        } else {
+            Block::Gap
+        },
+        objects: Vec::new(),
+    }
+}
+
+/// Prints every range of a layout with its label and the first rows of its bytes, optionally
+/// colored with ANSI escape codes, followed by the shared structures and the issues found.
+pub fn print_hexmap(buffer: &[u8], coverage: &Coverage, rows: usize, color: bool) {
+    let layout = &coverage.layout;
+    for range in layout {
+        let label = format!(
+            "{:#08x}-{:#08x} {:>8} bytes  {}",
+            range.start,
+            range.end,
+            range.end - range.start,
+            range.label()
+        );
+        let [r, g, b] = range.block.color();
+        match color {
+            true => log!("\x1b[38;2;{};{};{}m{}\x1b[0m", r, g, b, label),
+            false => log!("{}", label),
+        }
+        let bytes = &buffer[range.start as usize..range.end as usize];
+        for (row, chunk) in bytes.chunks(16).take(rows).enumerate() {
+            let hex: Vec<String> = chunk.iter().map(|byte| format!("{:02x}", byte)).collect();
+            let text: String = chunk
+                .iter()
+                .map(|&byte| match byte {
+                    0x20..=0x7E => byte as char,
+                    _ => '.',
+                })
+                .collect();
+            log!(
+                "  {:06x}  {:<47}  {}",
+                range.start as usize + row * 16,
+                hex.join(" "),
+                text
+            );
+        }
+        let printed = bytes.len().min(rows * 16);
+        if rows > 0 && printed < bytes.len() {
+            log!("  ... {} more bytes", bytes.len() - printed);
+        }
+    }
+    log!();
+    let total = |block: Block| {
+        layout
+            .iter()
+            .filter(|range| range.block == block)
+            .map(|range| range.end - range.start)
+            .sum::<u64>()
+    };
+    log!(
+        "{} bytes in {} ranges, {} bytes of padding, {} bytes unreferenced",
+        buffer.len(),
+        layout.len(),
+        total(Block::Padding),
+        total(Block::Gap)
+    );
+    for shared in coverage.shared_structures() {
+        log!("{}", shared);
+    }
+    for issue in coverage.issues() {
+        log!("Warning: {}", issue);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(reader: &mut Cursor<Vec<u8>>, block: Block, start: u64, end: u64) {
+        reader.set_position(start);
+        mark(block, reader, |reader| reader.set_position(end));
+    }
+
+    fn blocks(coverage: &Coverage) -> Vec<(u64, u64, &'static str)> {
+        coverage
+            .layout
+            .iter()
+            .map(|range| (range.start, range.end, range.block.name()))
+            .collect()
+    }
+
+    #[test]
+    fn maps_padding_and_gaps() {
+        let mut buffer = vec![0; 0x40];
+        buffer[0x30..0x38].fill(0xFF);
+        let mut reader = Cursor::new(buffer.clone());
+        record();
+        read(&mut reader, Block::Header, 0x00, 0x08);
+        read(&mut reader, Block::Object, 0x10, 0x18);
+        read(&mut reader, Block::Object, 0x18, 0x28);
+        let coverage = finish(&buffer);
+        assert_eq!(
+            blocks(&coverage),
+            vec![
+                (0x00, 0x08, "SPrimObjectHeader"),
+                (0x08, 0x10, "Padding"),
+                (0x10, 0x28, "SPrimObject"),
+                (0x28, 0x40, "Unreferenced"),
+            ]
+        );
+        assert_eq!(coverage.issues().len(), 1);
+        assert!(coverage.overlaps.is_empty() && coverage.shared.is_empty());
+    }
+
+    #[test]
+    fn reports_overlaps() {
+        let buffer = vec![0; 0x20];
+        let mut reader = Cursor::new(buffer.clone());
+        record();
+        read(&mut reader, Block::Vertices, 0x00, 0x18);
+        read(&mut reader, Block::Indices, 0x10, 0x20);
+        let coverage = finish(&buffer);
+        assert_eq!(coverage.overlaps, vec![(0, 1)]);
+        assert_eq!(coverage.issues().len(), 1);
+    }
+
+    #[test]
+    fn merges_shared_sub_meshes() {
+        let buffer = vec![0; 0x30];
+        let mut reader = Cursor::new(buffer.clone());
+        record();
+        for object in 0..2 {
+            set_object(Some(object));
+            read(&mut reader, Block::SubMeshTable, 0x00, 0x10);
+            read(&mut reader, Block::SubMesh, 0x10, 0x20);
+            read(
+                &mut reader,
+                Block::Object,
+                0x20 + object as u64 * 8,
+                0x28 + object as u64 * 8,
+            );
+        }
+        let coverage = finish(&buffer);
+        assert_eq!(coverage.layout.len(), 4);
+        assert_eq!(coverage.layout[0].objects, vec![0, 1]);
+        assert_eq!(coverage.layout[1].objects, vec![0, 1]);
+        // Only the table is listed, its sub-mesh is shared along with it.
+        assert_eq!(coverage.shared, vec![0]);
+        assert_eq!(
+            coverage.shared_structures(),
+            vec!["Objects 0, 1 share the sub-mesh table at 0x0"]
+        );
+        assert!(coverage.issues().is_empty());
+    }
+}
@@ -0,0 +1,171 @@
+use super::{Prim, SPrimObject};
+use crate::hash;
+use crate::log;
+use std::collections::BTreeSet;
+use std::path::Path;
+
+/// A material given by its material_id, or by the hash of its MATI resource in the reference table.
+#[derive(Clone, Copy)]
+enum Material {
+    Id(u16),
+    Hash(u64),
+}
+
+impl Material {
+    /// Hashes are written with 16 hex digits or a 0x prefix, so hashes of only decimal digits are
+    /// not read as material_ids.
+    fn parse(value: &str) -> Material {
+        let value = value.trim();
+        let is_hash = value.starts_with("0x")
+            || value.len() == 16 && value.chars().all(|c| c.is_ascii_hexdigit());
+        match value.parse::<u16>() {
+            Ok(id) if !is_hash => Material::Id(id),
+            _ => Material::Hash(hash::parse_resource_id(value)),
+        }
+    }
+}
+
+/// Material remapping read from a text file of FROM=TO lines. Each side is a decimal
+/// material_id, a MATI resource hash of 16 hex digits or a resource path, and `#` starts a
+/// comment.
+pub struct MaterialMap {
+    entries: Vec<(Material, Material)>,
+}
+
+impl MaterialMap {
+    pub fn load(path: &Path) -> log::Result<MaterialMap> {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) => {
+                return Err(log::error(format!(
+                    "Error opening file {}: {}",
+                    path.to_str().unwrap(),
+                    err
+                ))
+                .in_file(path));
+            }
+        };
+        let mut entries = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let Some((from, to)) = line.split_once('=') else {
+                return Err(log::error(format!(
+                    "Error: Material map entry on line {} must be FROM=TO: {}",
+                    i + 1,
+                    line
+                ))
+                .in_file(path));
+            };
+            entries.push((Material::parse(from), Material::parse(to)));
+        }
+        Ok(MaterialMap { entries })
+    }
+
+    fn uses_hashes(&self) -> bool {
+        self.entries
+            .iter()
+            .any(|entry| matches!(entry, (Material::Hash(_), _) | (_, Material::Hash(_))))
+    }
+}
+
+impl Prim {
+    /// The material_ids used by the objects and their sub-meshes.
+    pub fn material_ids(&self) -> Vec<u16> {
+        let mut ids = BTreeSet::new();
+        for object in &self.header.objects {
+            ids.insert(object.object().material_id);
+            if let Some(sub_mesh) = object.mesh().sub_mesh.as_ref() {
+                ids.insert(sub_mesh.object.material_id);
+            }
+        }
+        ids.into_iter().collect()
+    }
+
+    /// Remaps the material_id of every object and sub-mesh. MATI hashes are resolved against the
+    /// reference table, and target materials missing from it are added as new references.
+    pub fn map_materials(
+        &mut self,
+        map: &MaterialMap,
+        references: &mut Vec<(u64, u8)>,
+    ) -> log::Result<()> {
+        if map.uses_hashes() && references.is_empty() {
+            return Err(log::error(String::from(
+                "Error: The material map uses MATI hashes but the input PRIM has no resource meta",
+            )));
+        }
+        let input_materials = self.materials(references);
+        let mut materials = input_materials.clone();
+        let mut ids = Vec::new();
+        for (from, to) in &map.entries {
+            let from = match *from {
+                Material::Id(id) => id,
+                Material::Hash(hash) => match materials.iter().position(|h| *h == hash) {
+                    Some(id) => id as u16,
+                    None => {
+                        log!(
+                            "Warning: Material {:016X} of the material map is not in the reference table",
+                            hash
+                        );
+                        continue;
+                    }
+                },
+            };
+            let to = match *to {
+                Material::Id(id) => id,
+                Material::Hash(hash) => match materials.iter().position(|h| *h == hash) {
+                    Some(id) => id as u16,
+                    None => {
+                        let flags = references
+                            .iter()
+                            .find(|(h, _)| materials.get(from as usize) == Some(h))
+                            .unwrap_or(references.last().unwrap())
+                            .1;
+                        log!("Adding material {:016X} to the reference table", hash);
+                        references.push((hash, flags));
+                        materials.push(hash);
+                        (materials.len() - 1) as u16
+                    }
+                },
+            };
+            ids.push((from, to));
+        }
+        let mut unmapped = BTreeSet::new();
+        let mut map_object = |object: &mut SPrimObject| match ids
+            .iter()
+            .find(|(from, _)| *from == object.material_id)
+        {
+            Some((_, to)) => {
+                object.material_id = *to;
+                true
+            }
+            None => {
+                unmapped.insert(object.material_id);
+                false
+            }
+        };
+        let mut count = 0;
+        for object in &mut self.header.objects {
+            let mesh = object.mesh_mut();
+            map_object(&mut mesh.sub_mesh.as_mut().unwrap().object);
+            let mapped = map_object(&mut mesh.object);
+            if mapped {
+                count += 1;
+            }
+        }
+        log!("Mapped the materials of {} objects", count);
+        for id in unmapped {
+            match input_materials.get(id as usize) {
+                Some(hash) => log!(
+                    "Warning: Material id {} ({:016X}) is not in the material map",
+                    id,
+                    hash
+                ),
+                None => log!("Warning: Material id {} is not in the material map", id),
+            }
+        }
+        Ok(())
+    }
+}
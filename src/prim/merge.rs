@@ -0,0 +1,93 @@
+use super::{Object, Prim, SPrimMesh};
+use crate::log;
+use std::collections::HashMap;
+use std::path::Path;
+
+impl Prim {
+    pub fn merge(
+        &mut self,
+        other: Prim,
+        other_path: &Path,
+        bone_map: &HashMap<u8, u8>,
+    ) -> log::Result<()> {
+        let mut other = other.header;
+        if self.header.kind() != other.kind() {
+            return Err(log::error(format!(
+                "Error: Cannot merge {} and {} PRIMs: {} is {} but the first input PRIM is {}",
+                self.header.kind(),
+                other.kind(),
+                other_path.to_str().unwrap(),
+                other.kind(),
+                self.header.kind(),
+            )));
+        }
+        if self.header.is_weighted()
+            && self.header.bone_rig_resource_index != other.bone_rig_resource_index
+        {
+            if bone_map.is_empty() {
+                return Err(log::error(format!(
+                    "Error: {} uses bone rig resource index {:#x} but the first input PRIM uses {:#x}\nUse -b FROM=TO to map its bones onto the first input PRIM's bone rig",
+                    other_path.to_str().unwrap(),
+                    other.bone_rig_resource_index,
+                    self.header.bone_rig_resource_index,
+                )));
+            }
+            // Only the bones of the vertices are remapped, the bone tables index the bone rig in
+            // a layout that is not known.
+            for (o, object) in other.objects.iter_mut().enumerate() {
+                if let Object::Weighted(object) = object {
+                    if object.copy_bones.is_some()
+                        || object.bone_indicies.is_some()
+                        || object.bone_info.is_some()
+                    {
+                        return Err(log::error(format!(
+                            "Error: Object {} of {} has bone tables that cannot be mapped onto another bone rig",
+                            o,
+                            other_path.to_str().unwrap()
+                        )));
+                    }
+                    remap_bones(&mut object.mesh, bone_map);
+                }
+            }
+        }
+        self.header.header_flags |= other.header_flags;
+        for i in 0..3 {
+            self.header.bounding_box_min[i] =
+                self.header.bounding_box_min[i].min(other.bounding_box_min[i]);
+            self.header.bounding_box_max[i] =
+                self.header.bounding_box_max[i].max(other.bounding_box_max[i]);
+        }
+        // Shared sub-meshes are keyed by their offset in the file they were read from, move the
+        // keys of the other file past the keys in use so they stay apart.
+        let base = self
+            .header
+            .objects
+            .iter()
+            .filter_map(|object| object.mesh().shared_sub_mesh)
+            .max()
+            .map_or(0, |offset| offset + 1);
+        for object in &mut other.objects {
+            if let Some(offset) = object.mesh_mut().shared_sub_mesh.as_mut() {
+                *offset += base;
+            }
+        }
+        self.header.objects.append(&mut other.objects);
+        self.header.num_objects = self.header.objects.len() as u32;
+        Ok(())
+    }
+}
+
+fn remap_bones(mesh: &mut SPrimMesh, bone_map: &HashMap<u8, u8>) {
+    let vertices = mesh
+        .sub_mesh
+        .as_mut()
+        .and_then(|sub_mesh| sub_mesh.vertices.as_mut());
+    if let Some(vertices) = vertices {
+        for bone in &mut vertices.bones {
+            bone.0
+                .iter_mut()
+                .chain(bone.1.iter_mut())
+                .for_each(|x| *x = *bone_map.get(x).unwrap_or(x));
+        }
+    }
+}
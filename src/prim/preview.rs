@@ -0,0 +1,72 @@
+use super::Prim;
+
+/// Triangles of one object, with the vertex attributes the GUI preview draws.
+pub struct PreviewMesh {
+    pub name: String,
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    pub uvs: Vec<[f32; 2]>,
+    /// Per-vertex colors, or color1 repeated when the mesh has no color stream
+    pub colors: Vec<[u8; 4]>,
+    pub indices: Vec<u32>,
+}
+
+impl Prim {
+    /// The objects as preview meshes, in object order.
+    pub fn preview_meshes(&self) -> Vec<PreviewMesh> {
+        let mut meshes = Vec::new();
+        for (i, object) in self.header.objects.iter().enumerate() {
+            let mesh = object.mesh();
+            let sub_mesh = mesh.sub_mesh.as_ref().unwrap();
+            let (Some(vertices), Some(indices)) = (&sub_mesh.vertices, &sub_mesh.indices) else {
+                continue;
+            };
+            let num_vertices = vertices.positions.len();
+            // Triangles indexing past the vertices of a malformed PRIM are not drawn.
+            let (triangles, invalid): (Vec<_>, Vec<_>) = indices.indices
+                [..sub_mesh.num_indices as usize]
+                .chunks_exact(3)
+                .map(|triangle| {
+                    triangle
+                        .iter()
+                        .map(|index| *index as u32)
+                        .collect::<Vec<_>>()
+                })
+                .partition(|triangle| {
+                    triangle
+                        .iter()
+                        .all(|index| (*index as usize) < num_vertices)
+                });
+            if !invalid.is_empty() {
+                log!(
+                    "Warning: Object {} has {} triangles with vertex indices out of range, they are not previewed",
+                    i,
+                    invalid.len()
+                );
+            }
+            let colors = if vertices.colors.len() == num_vertices {
+                vertices.colors.clone()
+            } else {
+                let color1 = sub_mesh.object.color1.or(mesh.object.color1);
+                vec![color1.map_or([0xFF; 4], u32::to_le_bytes); num_vertices]
+            };
+            meshes.push(PreviewMesh {
+                name: format!("Object {}", i),
+                positions: vertices
+                    .positions
+                    .iter()
+                    .map(|position| [position[0], position[1], position[2]])
+                    .collect(),
+                normals: vertices
+                    .normals
+                    .iter()
+                    .map(|normal| [normal[0], normal[1], normal[2]])
+                    .collect(),
+                uvs: vertices.uvs.first().cloned().unwrap_or_default(),
+                colors,
+                indices: triangles.concat(),
+            });
+        }
+        meshes
+    }
+}
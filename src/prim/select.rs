@@ -0,0 +1,62 @@
+use super::{Prim, SPrimObject};
+use crate::log;
+
+#[derive(Default)]
+pub struct Selection {
+    pub indices: Vec<usize>,
+    pub material_ids: Vec<u16>,
+    pub lod_mask: Option<u8>,
+    pub variant_ids: Vec<u8>,
+}
+
+impl Selection {
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+            && self.material_ids.is_empty()
+            && self.lod_mask.is_none()
+            && self.variant_ids.is_empty()
+    }
+
+    fn matches(&self, index: usize, object: &SPrimObject) -> bool {
+        (self.indices.is_empty() || self.indices.contains(&index))
+            && (self.material_ids.is_empty() || self.material_ids.contains(&object.material_id))
+            && (self.lod_mask.is_none() || object.lod_mask & self.lod_mask.unwrap() != 0)
+            && (self.variant_ids.is_empty() || self.variant_ids.contains(&object.variant_id))
+    }
+}
+
+impl Prim {
+    pub fn extract(&mut self, selection: &Selection) -> log::Result<usize> {
+        self.retain_objects(selection, true)
+    }
+
+    pub fn remove(&mut self, selection: &Selection) -> log::Result<usize> {
+        self.retain_objects(selection, false)
+    }
+
+    fn retain_objects(&mut self, selection: &Selection, keep_selected: bool) -> log::Result<usize> {
+        let mut index = 0;
+        self.header.objects.retain(|object| {
+            let selected = selection.matches(index, object.object());
+            index += 1;
+            selected == keep_selected
+        });
+        let num_objects = self.header.objects.len();
+        if num_objects == 0 {
+            let message = if keep_selected {
+                "Error: No objects matched the selection"
+            } else {
+                "Error: No objects would be left in the output PRIM"
+            };
+            return Err(log::error(String::from(message)));
+        }
+        let num_selected = if keep_selected {
+            num_objects
+        } else {
+            self.header.num_objects as usize - num_objects
+        };
+        self.header.num_objects = num_objects as u32;
+        self.header.update_bounding_box();
+        Ok(num_selected)
+    }
+}
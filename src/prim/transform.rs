@@ -0,0 +1,293 @@
+use super::{Collision, Prim, SPrimMesh};
+use crate::log;
+
+#[derive(Clone, Copy)]
+pub struct Transform {
+    matrix: [[f32; 4]; 4],
+}
+
+impl Transform {
+    pub fn identity() -> Transform {
+        let mut matrix = [[0.0; 4]; 4];
+        for (i, row) in matrix.iter_mut().enumerate() {
+            row[i] = 1.0;
+        }
+        Transform { matrix }
+    }
+
+    pub fn from_rows(rows: [f32; 16]) -> Transform {
+        let mut matrix = [[0.0; 4]; 4];
+        for (i, row) in matrix.iter_mut().enumerate() {
+            row.copy_from_slice(&rows[i * 4..i * 4 + 4]);
+        }
+        Transform { matrix }
+    }
+
+    pub fn scale(self, scale: [f32; 3]) -> Transform {
+        let mut matrix = Transform::identity();
+        for (i, x) in scale.iter().enumerate() {
+            matrix.matrix[i][i] = *x;
+        }
+        self.then(matrix)
+    }
+
+    pub fn rotate(self, degrees: [f32; 3]) -> Transform {
+        let mut transform = self;
+        for (axis, angle) in degrees.iter().enumerate() {
+            let (sin, cos) = angle.to_radians().sin_cos();
+            let (a, b) = ((axis + 1) % 3, (axis + 2) % 3);
+            let mut matrix = Transform::identity();
+            matrix.matrix[a][a] = cos;
+            matrix.matrix[a][b] = -sin;
+            matrix.matrix[b][a] = sin;
+            matrix.matrix[b][b] = cos;
+            transform = transform.then(matrix);
+        }
+        transform
+    }
+
+    pub fn mirror(self, axis: usize) -> Transform {
+        let mut scale = [1.0; 3];
+        scale[axis] = -1.0;
+        self.scale(scale)
+    }
+
+    pub fn translate(self, translation: [f32; 3]) -> Transform {
+        let mut matrix = Transform::identity();
+        for (i, x) in translation.iter().enumerate() {
+            matrix.matrix[i][3] = *x;
+        }
+        self.then(matrix)
+    }
+
+    /// Returns the transform that applies `self` followed by `next`.
+    pub fn then(self, next: Transform) -> Transform {
+        let mut matrix = [[0.0; 4]; 4];
+        for (i, row) in matrix.iter_mut().enumerate() {
+            for (j, x) in row.iter_mut().enumerate() {
+                *x = (0..4).map(|k| next.matrix[i][k] * self.matrix[k][j]).sum();
+            }
+        }
+        Transform { matrix }
+    }
+
+    fn apply_position(&self, position: &mut [f32; 4]) {
+        let mut transformed = [0.0; 3];
+        for (i, x) in transformed.iter_mut().enumerate() {
+            *x = (0..3).map(|j| self.matrix[i][j] * position[j]).sum::<f32>() + self.matrix[i][3];
+        }
+        position[0..3].copy_from_slice(&transformed);
+    }
+
+    fn determinant(&self) -> f32 {
+        let m = &self.matrix;
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+
+    /// Inverse transpose of the upper 3x3, used for normals, tangents and bitangents.
+    fn normal_matrix(&self) -> [[f32; 3]; 3] {
+        let m = &self.matrix;
+        let determinant = self.determinant();
+        let mut normal_matrix = [[0.0; 3]; 3];
+        for (i, row) in normal_matrix.iter_mut().enumerate() {
+            for (j, x) in row.iter_mut().enumerate() {
+                let (i1, i2) = ((i + 1) % 3, (i + 2) % 3);
+                let (j1, j2) = ((j + 1) % 3, (j + 2) % 3);
+                *x = (m[i1][j1] * m[i2][j2] - m[i1][j2] * m[i2][j1]) / determinant;
+            }
+        }
+        normal_matrix
+    }
+}
+
+fn apply_direction(normal_matrix: &[[f32; 3]; 3], direction: &mut [f32; 4]) {
+    let mut transformed = [0.0; 3];
+    for (i, x) in transformed.iter_mut().enumerate() {
+        *x = (0..3).map(|j| normal_matrix[i][j] * direction[j]).sum();
+    }
+    let length = transformed.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if length > 0.0 {
+        transformed.iter_mut().for_each(|x| *x /= length);
+    }
+    direction[0..3].copy_from_slice(&transformed);
+}
+
+impl Prim {
+    pub fn transform(&mut self, transform: &Transform) -> log::Result<()> {
+        // A singular matrix flattens the meshes and has no normal matrix.
+        if !transform.determinant().is_normal() {
+            return Err(log::error(String::from(
+                "Error: The transform matrix is singular, its determinant is 0",
+            )));
+        }
+        let normal_matrix = transform.normal_matrix();
+        let flip_winding = transform.determinant() < 0.0;
+        for object in &mut self.header.objects {
+            let mesh = object.mesh_mut();
+            let sub_mesh = mesh.sub_mesh.as_mut().unwrap();
+            if let Some(vertices) = sub_mesh.vertices.as_mut() {
+                for position in &mut vertices.positions {
+                    transform.apply_position(position);
+                }
+                for direction in vertices
+                    .normals
+                    .iter_mut()
+                    .chain(vertices.tangents.iter_mut())
+                    .chain(vertices.bitangents.iter_mut())
+                {
+                    apply_direction(&normal_matrix, direction);
+                }
+            }
+            if flip_winding {
+                if let Some(indices) = sub_mesh.indices.as_mut() {
+                    let num_indices = sub_mesh.num_indices as usize;
+                    for triangle in indices.indices[..num_indices].chunks_exact_mut(3) {
+                        triangle.swap(1, 2);
+                    }
+                }
+            }
+            mesh.update_bounds();
+        }
+        self.header.update_bounding_box();
+        Ok(())
+    }
+}
+
+impl SPrimMesh {
+    /// Refreshes the bounding boxes, position scale/bias and collision boxes from the vertices.
+    fn update_bounds(&mut self) {
+        let sub_mesh = self.sub_mesh.as_mut().unwrap();
+        let positions = match sub_mesh.vertices.as_ref() {
+            Some(vertices) if !vertices.positions.is_empty() => &vertices.positions,
+            _ => return,
+        };
+        let mut min = [f32::MAX; 3];
+        let mut max = [f32::MIN; 3];
+        for position in positions {
+            for i in 0..3 {
+                min[i] = min[i].min(position[i]);
+                max[i] = max[i].max(position[i]);
+            }
+        }
+        if let (Some(collision), Some(indices)) =
+            (sub_mesh.collision.as_mut(), sub_mesh.indices.as_ref())
+        {
+            let num_indices = sub_mesh.num_indices as usize;
+            collision.rebuild(positions, &indices.indices[..num_indices], &min, &max);
+        }
+        sub_mesh.object.bounding_box_min = min;
+        sub_mesh.object.bounding_box_max = max;
+        self.object.bounding_box_min = min;
+        self.object.bounding_box_max = max;
+        for i in 0..3 {
+            self.position_bias[i] = min[i];
+            self.position_scale[i] = max[i] - min[i];
+        }
+    }
+}
+
+impl Collision {
+    /// Recomputes each box from its `triangles_per_box` triangles, quantized to the sub-mesh bounds.
+    fn rebuild(&mut self, positions: &[[f32; 4]], indices: &[u16], min: &[f32; 3], max: &[f32; 3]) {
+        if self.triangles_per_box == 0 {
+            return;
+        }
+        let quantize = |value: f32, i: usize| {
+            let extent = max[i] - min[i];
+            if extent > 0.0 {
+                f32::round(255.0 * (value - min[i]) / extent) as u8
+            } else {
+                0
+            }
+        };
+        self.bounding_boxes = indices
+            .chunks(self.triangles_per_box as usize * 3)
+            .map(|chunk| {
+                let mut bounding_box = [[u8::MAX; 3], [u8::MIN; 3]];
+                for index in chunk {
+                    let position = &positions[*index as usize];
+                    for i in 0..3 {
+                        let value = quantize(position[i], i);
+                        bounding_box[0][i] = bounding_box[0][i].min(value);
+                        bounding_box[1][i] = bounding_box[1][i].max(value);
+                    }
+                }
+                bounding_box
+            })
+            .collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: &[f32], b: &[f32]) {
+        for (a, b) in a.iter().zip(b) {
+            assert!((a - b).abs() < 1e-5, "{:?} != {:?}", a, b);
+        }
+    }
+
+    fn apply(transform: &Transform, position: [f32; 3]) -> [f32; 4] {
+        let mut position = [position[0], position[1], position[2], 1.0];
+        transform.apply_position(&mut position);
+        position
+    }
+
+    #[test]
+    fn transforms_apply_in_order() {
+        let transform = Transform::identity()
+            .scale([2.0, 2.0, 2.0])
+            .translate([1.0, 0.0, 0.0]);
+        assert_close(&apply(&transform, [1.0, 1.0, 1.0]), &[3.0, 2.0, 2.0, 1.0]);
+        let transform = Transform::identity()
+            .translate([1.0, 0.0, 0.0])
+            .scale([2.0, 2.0, 2.0]);
+        assert_close(&apply(&transform, [1.0, 1.0, 1.0]), &[4.0, 2.0, 2.0, 1.0]);
+    }
+
+    #[test]
+    fn rotates_counterclockwise() {
+        let transform = Transform::identity().rotate([0.0, 0.0, 90.0]);
+        assert_close(&apply(&transform, [1.0, 0.0, 0.0]), &[0.0, 1.0, 0.0, 1.0]);
+        let transform = Transform::identity().rotate([90.0, 0.0, 0.0]);
+        assert_close(&apply(&transform, [0.0, 1.0, 0.0]), &[0.0, 0.0, 1.0, 1.0]);
+        assert_close(&[transform.determinant()], &[1.0]);
+    }
+
+    #[test]
+    fn from_rows_reads_row_major() {
+        let transform = Transform::from_rows([
+            1.0, 0.0, 0.0, 5.0, //
+            0.0, 1.0, 0.0, 6.0, //
+            0.0, 0.0, 1.0, 7.0, //
+            0.0, 0.0, 0.0, 1.0,
+        ]);
+        assert_close(&apply(&transform, [0.0, 0.0, 0.0]), &[5.0, 6.0, 7.0, 1.0]);
+    }
+
+    #[test]
+    fn determinant_flags_mirrors_and_flattening() {
+        assert!(Transform::identity().mirror(1).determinant() < 0.0);
+        let flat = Transform::identity().scale([1.0, 0.0, 1.0]);
+        assert!(!flat.determinant().is_normal());
+        let scaled = Transform::identity().scale([2.0, 3.0, 4.0]);
+        assert_close(&[scaled.determinant()], &[24.0]);
+    }
+
+    #[test]
+    fn normal_matrix_is_inverse_transpose() {
+        let transform = Transform::identity().scale([2.0, 1.0, 1.0]);
+        let normal_matrix = transform.normal_matrix();
+        assert_close(&normal_matrix[0], &[0.5, 0.0, 0.0]);
+        assert_close(&normal_matrix[1], &[0.0, 1.0, 0.0]);
+        assert_close(&normal_matrix[2], &[0.0, 0.0, 1.0]);
+        // Directions are normalized after the transform.
+        let mut normal = [1.0, 1.0, 0.0, 0.0];
+        apply_direction(&normal_matrix, &mut normal);
+        let length = 1.25f32.sqrt();
+        assert_close(&normal, &[0.5 / length, 1.0 / length, 0.0, 0.0]);
+    }
+}
@@ -0,0 +1,93 @@
+use super::log;
+use super::prim::Packing;
+use super::GameVersion;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Conversion decisions applied to a PRIM before it is written in the output game version.
+/// Profile files are TOML and only need to name the decisions they override.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    /// num_uv_channels written for weighted sub-meshes with a single UV channel
+    pub weighted_num_uv_channels: u32,
+    /// num_uv_channels written for static sub-meshes with a single UV channel
+    pub static_num_uv_channels: u32,
+    /// color1 written when the input game version does not store one
+    pub default_color1: u32,
+    /// Keep num_indices_extra from the input instead of writing default_num_indices_extra
+    pub keep_num_indices_extra: bool,
+    /// num_indices_extra written when it is not kept or the input does not store one
+    pub default_num_indices_extra: u32,
+    /// Vertex packing of mesh objects, which rewrites draw_destination
+    pub packing: Packing,
+    /// Remove weighted meshes that reference cloth without embedding it
+    pub remove_cloth: bool,
+}
+
+impl Profile {
+    /// The built-in profile of a version pair. Inputs of HMA and ALPHA store no
+    /// num_indices_extra, so it is only kept from HM2016 and WOA inputs.
+    pub fn builtin(input_version: &GameVersion, output_version: &GameVersion) -> Profile {
+        Profile {
+            weighted_num_uv_channels: match output_version {
+                GameVersion::Hma | GameVersion::Alpha | GameVersion::Hm2016 => 0,
+                GameVersion::Woa => 1,
+            },
+            static_num_uv_channels: 1,
+            default_color1: 0,
+            keep_num_indices_extra: match input_version {
+                GameVersion::Hma | GameVersion::Alpha => false,
+                GameVersion::Hm2016 | GameVersion::Woa => true,
+            },
+            default_num_indices_extra: 0,
+            packing: Packing::Auto,
+            remove_cloth: false,
+        }
+    }
+
+    /// Reads a profile file, taking every decision it does not name from the built-in profile.
+    pub fn load(
+        path: &Path,
+        input_version: &GameVersion,
+        output_version: &GameVersion,
+    ) -> log::Result<Profile> {
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) => {
+                return Err(log::error(format!(
+                    "Error opening file {}: {}",
+                    path.to_str().unwrap(),
+                    err
+                ))
+                .in_file(path));
+            }
+        };
+        let overrides = match contents.parse::<toml::Table>() {
+            Ok(overrides) => overrides,
+            Err(err) => {
+                return Err(log::error(format!(
+                    "Error parsing profile {}: {}",
+                    path.to_str().unwrap(),
+                    err
+                ))
+                .in_file(path));
+            }
+        };
+        let mut table =
+            toml::Table::try_from(Profile::builtin(input_version, output_version)).unwrap();
+        table.extend(overrides);
+        table.try_into().map_err(|err| {
+            log::error(format!(
+                "Error in profile {}: {}",
+                path.to_str().unwrap(),
+                err
+            ))
+            .in_file(path)
+        })
+    }
+
+    pub fn to_toml(&self) -> String {
+        toml::to_string(self).unwrap()
+    }
+}
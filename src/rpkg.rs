@@ -0,0 +1,489 @@
+#![allow(dead_code)]
+use super::hash;
+use super::io;
+use super::log;
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::fs::File;
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+mod meta;
+
+pub use meta::MetaFormat;
+
+/// Key cycled over the bytes of scrambled resources.
+const XOR_KEY: [u8; 8] = [0xDC, 0x45, 0xA6, 0x9C, 0xD3, 0x72, 0x4C, 0xAB];
+
+/// Resource in the archive index, with its resource info and reference table.
+#[derive(Clone)]
+pub struct Entry {
+    pub hash: u64,
+    data_offset: u64,
+    data_size: u32,
+    pub resource_type: String,
+    pub references: Vec<(u64, u8)>,
+    /// Top two bits of the reference count, kept so entries are written back as read
+    reference_count_flags: u32,
+    states_chunk_size: u32,
+    pub size: u32,
+    system_memory_requirement: u32,
+    video_memory_requirement: u32,
+    /// Compressed and scrambled data of resources added to a patch archive
+    stored: Option<Vec<u8>>,
+}
+
+impl Entry {
+    /// New scrambled resource without data or references.
+    pub fn new(hash: u64, resource_type: &str) -> Entry {
+        Entry {
+            hash,
+            data_offset: 0,
+            data_size: 0x80000000,
+            resource_type: resource_type.to_string(),
+            references: Vec::new(),
+            reference_count_flags: 0,
+            states_chunk_size: 0,
+            size: 0,
+            system_memory_requirement: 0,
+            video_memory_requirement: 0xFFFFFFFF,
+            stored: None,
+        }
+    }
+
+    fn read(reader: &mut Cursor<Vec<u8>>) -> Entry {
+        Entry {
+            hash: reader.read_u64::<LittleEndian>().unwrap(),
+            data_offset: reader.read_u64::<LittleEndian>().unwrap(),
+            data_size: reader.read_u32::<LittleEndian>().unwrap(),
+            resource_type: String::new(),
+            references: Vec::new(),
+            reference_count_flags: 0,
+            states_chunk_size: 0,
+            size: 0,
+            system_memory_requirement: 0,
+            video_memory_requirement: 0,
+            stored: None,
+        }
+    }
+
+    fn write(&self, writer: &mut Cursor<Vec<u8>>) {
+        writer.write_u64::<LittleEndian>(self.hash).unwrap();
+        writer.write_u64::<LittleEndian>(self.data_offset).unwrap();
+        writer.write_u32::<LittleEndian>(self.data_size).unwrap();
+    }
+
+    fn write_info(&self, writer: &mut Cursor<Vec<u8>>) {
+        let mut resource_type = self.resource_type.clone().into_bytes();
+        resource_type.reverse();
+        io::write_u8_into(writer, &resource_type);
+        writer
+            .write_u32::<LittleEndian>(self.references_chunk_size())
+            .unwrap();
+        writer
+            .write_u32::<LittleEndian>(self.states_chunk_size)
+            .unwrap();
+        writer.write_u32::<LittleEndian>(self.size).unwrap();
+        writer
+            .write_u32::<LittleEndian>(self.system_memory_requirement)
+            .unwrap();
+        writer
+            .write_u32::<LittleEndian>(self.video_memory_requirement)
+            .unwrap();
+        if !self.references.is_empty() {
+            writer
+                .write_u32::<LittleEndian>(
+                    self.references.len() as u32 | self.reference_count_flags,
+                )
+                .unwrap();
+            for (_, flags) in &self.references {
+                writer.write_u8(*flags).unwrap();
+            }
+            for (hash, _) in &self.references {
+                writer.write_u64::<LittleEndian>(*hash).unwrap();
+            }
+        }
+    }
+
+    fn read_info(&mut self, reader: &mut Cursor<Vec<u8>>) {
+        let mut resource_type = io::read_u8_array(reader, 4);
+        resource_type.reverse();
+        self.resource_type = String::from_utf8_lossy(&resource_type).to_string();
+        let references_chunk_size = reader.read_u32::<LittleEndian>().unwrap();
+        self.states_chunk_size = reader.read_u32::<LittleEndian>().unwrap();
+        self.size = reader.read_u32::<LittleEndian>().unwrap();
+        self.system_memory_requirement = reader.read_u32::<LittleEndian>().unwrap();
+        self.video_memory_requirement = reader.read_u32::<LittleEndian>().unwrap();
+        if references_chunk_size > 0 {
+            let num_references = reader.read_u32::<LittleEndian>().unwrap();
+            self.reference_count_flags = num_references & 0xC0000000;
+            let num_references = num_references & 0x3FFFFFFF;
+            let flags = io::read_u8_array(reader, num_references as usize);
+            self.references = flags
+                .into_iter()
+                .map(|flag| (reader.read_u64::<LittleEndian>().unwrap(), flag))
+                .collect();
+        }
+    }
+
+    fn references_chunk_size(&self) -> u32 {
+        if self.references.is_empty() {
+            0
+        } else {
+            4 + self.references.len() as u32 * 9
+        }
+    }
+
+    pub fn is_compressed(&self) -> bool {
+        self.compressed_size() > 0
+    }
+
+    pub fn is_scrambled(&self) -> bool {
+        self.data_size & 0x80000000 == 0x80000000
+    }
+
+    fn compressed_size(&self) -> u32 {
+        self.data_size & 0x3FFFFFFF
+    }
+}
+
+/// Index of an RPKG v1 (GKPR) or v2 (2KPR) archive. Resource data is read on demand.
+pub struct Rpkg {
+    path: PathBuf,
+    pub version: u8,
+    /// v2 only: unknown u32, chunk number, chunk type, patch number and language tag
+    v2_header: [u8; 9],
+    pub is_patch: bool,
+    pub deletions: Vec<u64>,
+    pub entries: Vec<Entry>,
+}
+
+impl Rpkg {
+    pub fn read(path: &Path) -> log::Result<Rpkg> {
+        let mut file = match File::open(path) {
+            Ok(file) => file,
+            Err(err) => {
+                return Err(log::error(format!(
+                    "Error opening file {}: {}",
+                    path.to_str().unwrap(),
+                    err
+                ))
+                .in_file(path));
+            }
+        };
+        let mut magic = [0; 4];
+        file.read_exact(&mut magic).unwrap();
+        let version = match &magic {
+            b"GKPR" => 1,
+            b"2KPR" => 2,
+            _ => {
+                return Err(log::error(format!(
+                    "Error: {} is not an RPKG archive",
+                    path.to_str().unwrap()
+                ))
+                .in_file(path));
+            }
+        };
+        let mut v2_header = [0; 9];
+        if version == 2 {
+            file.read_exact(&mut v2_header).unwrap();
+        }
+        let num_entries = file.read_u32::<LittleEndian>().unwrap();
+        let index_size = file.read_u32::<LittleEndian>().unwrap();
+        let info_size = file.read_u32::<LittleEndian>().unwrap();
+        // Patch archives are only told apart by their file name
+        let is_patch = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.to_lowercase().contains("patch"));
+        let mut deletions = Vec::new();
+        if is_patch {
+            let num_deletions = file.read_u32::<LittleEndian>().unwrap();
+            let mut buffer = vec![0; num_deletions as usize * 8];
+            file.read_exact(&mut buffer).unwrap();
+            let mut reader = Cursor::new(buffer);
+            for _ in 0..num_deletions {
+                deletions.push(reader.read_u64::<LittleEndian>().unwrap());
+            }
+        }
+        let mut buffer = vec![0; index_size as usize + info_size as usize];
+        if file.read_exact(&mut buffer).is_err() {
+            return Err(log::error(format!(
+                "Error: RPKG index of {} is truncated",
+                path.to_str().unwrap()
+            ))
+            .in_file(path));
+        }
+        let mut reader = Cursor::new(buffer);
+        let mut entries: Vec<Entry> = (0..num_entries).map(|_| Entry::read(&mut reader)).collect();
+        reader.seek(SeekFrom::Start(index_size as u64)).unwrap();
+        for entry in &mut entries {
+            entry.read_info(&mut reader);
+        }
+        Ok(Rpkg {
+            path: path.to_path_buf(),
+            version,
+            v2_header,
+            is_patch,
+            deletions,
+            entries,
+        })
+    }
+
+    /// Finds a resource by its hash in hex, e.g. 00123456789ABCDE, or by its resource path.
+    pub fn find(&self, resource: &str) -> log::Result<&Entry> {
+        let hash = hash::parse_resource_id(resource);
+        match self.entries.iter().find(|entry| entry.hash == hash) {
+            Some(entry) => Ok(entry),
+            None => Err(log::error(format!(
+                "Error: Resource {:016X} not found in {}",
+                hash,
+                self.path.to_str().unwrap()
+            ))
+            .in_file(&self.path)),
+        }
+    }
+
+    /// Reads the resource data, undoing the XOR scrambling and LZ4 compression.
+    pub fn data(&self, entry: &Entry) -> log::Result<Vec<u8>> {
+        let mut data = self.stored(entry)?;
+        if entry.is_scrambled() {
+            xor(&mut data);
+        }
+        if entry.is_compressed() {
+            data = match lz4_flex::block::decompress(&data, entry.size as usize) {
+                Ok(data) => data,
+                Err(err) => {
+                    return Err(log::error(format!(
+                        "Error decompressing resource {:016X}: {}",
+                        entry.hash, err
+                    ))
+                    .in_file(&self.path));
+                }
+            };
+        }
+        Ok(data)
+    }
+
+    /// Reads the resource data as stored in the archive.
+    fn stored(&self, entry: &Entry) -> log::Result<Vec<u8>> {
+        if let Some(stored) = &entry.stored {
+            return Ok(stored.clone());
+        }
+        let mut file = File::open(&self.path).unwrap();
+        let size = if entry.is_compressed() {
+            entry.compressed_size()
+        } else {
+            entry.size
+        };
+        let mut data = vec![0; size as usize];
+        file.seek(SeekFrom::Start(entry.data_offset)).unwrap();
+        if file.read_exact(&mut data).is_err() {
+            return Err(log::error(format!(
+                "Error: Data of resource {:016X} is truncated",
+                entry.hash
+            ))
+            .in_file(&self.path));
+        }
+        Ok(data)
+    }
+
+    /// Opens a patch archive to add resources to, keeping the resources it already holds.
+    pub fn open_patch(path: &Path, version: u8) -> log::Result<Rpkg> {
+        let name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or_default()
+            .to_lowercase();
+        // Archives are read as patches from their name, so one written without it could not be
+        // read back.
+        if !name.contains("patch") {
+            return Err(log::error(format!(
+                "Error: Patch archive {} must be named like chunk0patch1.rpkg",
+                path.to_str().unwrap()
+            ))
+            .in_file(path));
+        }
+        if !path.exists() {
+            let number = |prefix: &str| -> u8 {
+                name.split(prefix)
+                    .nth(1)
+                    .map(|rest| rest.chars().take_while(|c| c.is_ascii_digit()).collect())
+                    .and_then(|digits: String| digits.parse().ok())
+                    .unwrap_or(0)
+            };
+            let mut v2_header = [0; 9];
+            v2_header[0] = 1;
+            v2_header[4] = number("chunk");
+            v2_header[6] = number("patch");
+            v2_header[7..9].copy_from_slice(b"xx");
+            return Ok(Rpkg {
+                path: path.to_path_buf(),
+                version,
+                v2_header,
+                is_patch: true,
+                deletions: Vec::new(),
+                entries: Vec::new(),
+            });
+        }
+        let mut rpkg = Rpkg::read(path)?;
+        rpkg.is_patch = true;
+        let stored: Vec<Vec<u8>> = rpkg
+            .entries
+            .iter()
+            .map(|entry| rpkg.stored(entry))
+            .collect::<log::Result<_>>()?;
+        for (entry, stored) in rpkg.entries.iter_mut().zip(stored) {
+            entry.stored = Some(stored);
+        }
+        Ok(rpkg)
+    }
+
+    /// Adds a resource, replacing any resource with the same hash. The data is LZ4 compressed
+    /// and scrambled the way the game stores resources.
+    pub fn add(&mut self, hash: u64, resource_type: &str, references: Vec<(u64, u8)>, data: &[u8]) {
+        let mut stored = lz4_flex::block::compress(data);
+        xor(&mut stored);
+        self.entries.retain(|entry| entry.hash != hash);
+        self.deletions.retain(|deletion| *deletion != hash);
+        let mut entry = Entry::new(hash, resource_type);
+        entry.data_size |= stored.len() as u32;
+        entry.references = references;
+        entry.size = data.len() as u32;
+        entry.system_memory_requirement = data.len() as u32;
+        entry.stored = Some(stored);
+        self.entries.push(entry);
+    }
+
+    pub fn write(&mut self) -> log::Result<()> {
+        let mut infos = Cursor::new(Vec::new());
+        for entry in &self.entries {
+            entry.write_info(&mut infos);
+        }
+        let mut writer = Cursor::new(Vec::new());
+        match self.version {
+            1 => io::write_u8_into(&mut writer, b"GKPR"),
+            _ => {
+                io::write_u8_into(&mut writer, b"2KPR");
+                io::write_u8_into(&mut writer, &self.v2_header);
+            }
+        }
+        let index_size = self.entries.len() as u32 * 20;
+        writer
+            .write_u32::<LittleEndian>(self.entries.len() as u32)
+            .unwrap();
+        writer.write_u32::<LittleEndian>(index_size).unwrap();
+        writer
+            .write_u32::<LittleEndian>(infos.get_ref().len() as u32)
+            .unwrap();
+        if self.is_patch {
+            writer
+                .write_u32::<LittleEndian>(self.deletions.len() as u32)
+                .unwrap();
+            for deletion in &self.deletions {
+                writer.write_u64::<LittleEndian>(*deletion).unwrap();
+            }
+        }
+        let mut data_offset = writer.position() + index_size as u64 + infos.get_ref().len() as u64;
+        for entry in &mut self.entries {
+            entry.data_offset = data_offset;
+            data_offset += entry.stored.as_ref().unwrap().len() as u64;
+            entry.write(&mut writer);
+        }
+        io::write_u8_into(&mut writer, infos.get_ref());
+        for entry in &self.entries {
+            io::write_u8_into(&mut writer, entry.stored.as_ref().unwrap());
+        }
+        let mut file = match File::create(&self.path) {
+            Ok(file) => file,
+            Err(err) => {
+                return Err(log::error(format!(
+                    "Error creating file {}: {}",
+                    self.path.to_str().unwrap(),
+                    err
+                ))
+                .in_file(&self.path));
+            }
+        };
+        file.write_all(writer.get_ref()).unwrap();
+        Ok(())
+    }
+}
+
+fn xor(data: &mut [u8]) {
+    for (i, x) in data.iter_mut().enumerate() {
+        *x ^= XOR_KEY[i % XOR_KEY.len()];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn patch_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("primport-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join("chunk0patch2.rpkg")
+    }
+
+    #[test]
+    fn patch_round_trip() {
+        let path = patch_path("rpkg");
+        let mut rpkg = Rpkg::open_patch(&path, 2).unwrap();
+        let data: Vec<u8> = (0..=255).cycle().take(1000).collect();
+        rpkg.add(
+            0x00123456789ABCDE,
+            "PRIM",
+            vec![(0x00ABCDEF01234567, 0x1F)],
+            &data,
+        );
+        rpkg.add(0x0011111111111111, "MATI", Vec::new(), b"material");
+        rpkg.deletions.push(0x0022222222222222);
+        rpkg.write().unwrap();
+
+        let read = Rpkg::read(&path).unwrap();
+        assert_eq!(read.version, 2);
+        assert!(read.is_patch);
+        assert_eq!(read.v2_header[4], 0);
+        assert_eq!(read.v2_header[6], 2);
+        assert_eq!(read.deletions, vec![0x0022222222222222]);
+        let entry = read.find("00123456789ABCDE").unwrap();
+        assert_eq!(entry.resource_type, "PRIM");
+        assert_eq!(entry.references, vec![(0x00ABCDEF01234567, 0x1F)]);
+        assert!(entry.is_scrambled() && entry.is_compressed());
+        assert_eq!(read.data(entry).unwrap(), data);
+        assert_eq!(
+            read.data(read.find("0011111111111111").unwrap()).unwrap(),
+            b"material"
+        );
+
+        // Reopening keeps the resources already in the patch.
+        let mut rpkg = Rpkg::open_patch(&path, 2).unwrap();
+        rpkg.add(0x0011111111111111, "MATI", Vec::new(), b"replaced");
+        rpkg.write().unwrap();
+        let read = Rpkg::read(&path).unwrap();
+        assert_eq!(read.entries.len(), 2);
+        assert_eq!(
+            read.data(read.find("00123456789ABCDE").unwrap()).unwrap(),
+            data
+        );
+        assert_eq!(
+            read.data(read.find("0011111111111111").unwrap()).unwrap(),
+            b"replaced"
+        );
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn info_keeps_reference_count_flags() {
+        let mut entry = Entry::new(0x00123456789ABCDE, "PRIM");
+        entry.references = vec![(1, 0x1F), (2, 0x80)];
+        entry.reference_count_flags = 0xC0000000;
+        let mut writer = Cursor::new(Vec::new());
+        entry.write_info(&mut writer);
+        let mut read = Entry::new(0, "");
+        read.read_info(&mut Cursor::new(writer.into_inner()));
+        assert_eq!(read.resource_type, "PRIM");
+        assert_eq!(read.references, entry.references);
+        assert_eq!(read.reference_count_flags, 0xC0000000);
+    }
+}
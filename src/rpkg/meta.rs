@@ -0,0 +1,246 @@
+use super::Entry;
+use crate::log;
+use crate::GameVersion;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{Cursor, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Format of the meta file accompanying an extracted resource.
+#[derive(Clone, Copy)]
+pub enum MetaFormat {
+    /// `.meta`: the RPKG index entry followed by the resource info and reference table
+    Binary,
+    /// `.meta.json`: the same fields as JSON
+    Json,
+}
+
+impl MetaFormat {
+    fn path(&self, resource: &Path) -> PathBuf {
+        let extension = match self {
+            MetaFormat::Binary => "meta",
+            MetaFormat::Json => "meta.json",
+        };
+        PathBuf::from(format!("{}.{}", resource.to_str().unwrap(), extension))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct MetaJson {
+    hash_value: String,
+    hash_offset: u64,
+    hash_size: u32,
+    hash_resource_type: String,
+    hash_reference_table_size: u32,
+    hash_reference_table_dummy: u32,
+    hash_size_final: u32,
+    hash_size_in_memory: u32,
+    hash_size_in_video_memory: u32,
+    hash_reference_data: Vec<ReferenceJson>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ReferenceJson {
+    hash: String,
+    flag: String,
+}
+
+fn parse_hex<T>(
+    value: &str,
+    parse: fn(&str, u32) -> Result<T, std::num::ParseIntError>,
+) -> log::Result<T> {
+    parse(value.trim_start_matches("0x"), 16)
+        .map_err(|err| log::error(format!("Error: Meta value {} is not hex: {}", value, err)))
+}
+
+/// Reference flags of HMA, Alpha and HM2016 resources.
+fn is_legacy(version: &GameVersion) -> bool {
+    match version {
+        GameVersion::Hma | GameVersion::Alpha | GameVersion::Hm2016 => true,
+        GameVersion::Woa => false,
+    }
+}
+
+/// Legacy flags: 0x1 runtime acquired, 0x2 weak reference, 0x20 install dependency.
+/// Standard flags: 0x1F language code, 0x20 acquired, 0xC0 type (0 install, 1 normal, 2 weak).
+fn legacy_to_standard(flags: u8) -> u8 {
+    let reference_type = if flags & 0x20 != 0 {
+        0
+    } else if flags & 0x2 != 0 {
+        2
+    } else {
+        1
+    };
+    0x1F | (flags & 0x1) << 5 | reference_type << 6
+}
+
+fn standard_to_legacy(flags: u8) -> u8 {
+    let reference_type = match flags >> 6 {
+        0 => 0x20,
+        2 => 0x2,
+        _ => 0,
+    };
+    (flags >> 5) & 0x1 | reference_type
+}
+
+impl Entry {
+    /// Reads the `.meta` or `.meta.json` file next to an extracted resource, if there is one.
+    pub fn read_meta_for(resource: &Path) -> log::Result<Option<(Entry, MetaFormat)>> {
+        let meta = [MetaFormat::Binary, MetaFormat::Json]
+            .into_iter()
+            .map(|format| (format.path(resource), format))
+            .find(|(path, _)| path.exists());
+        match meta {
+            Some((path, format)) => Ok(Some((Entry::read_meta(&path, format)?, format))),
+            None => Ok(None),
+        }
+    }
+
+    pub fn read_meta(path: &Path, format: MetaFormat) -> log::Result<Entry> {
+        let mut file = match File::open(path) {
+            Ok(file) => file,
+            Err(err) => {
+                return Err(log::error(format!(
+                    "Error opening file {}: {}",
+                    path.to_str().unwrap(),
+                    err
+                ))
+                .in_file(path));
+            }
+        };
+        let mut buffer = Vec::new();
+        file.read_to_end(&mut buffer).unwrap();
+        match format {
+            MetaFormat::Binary => {
+                let mut reader = Cursor::new(buffer);
+                let mut entry = Entry::read(&mut reader);
+                entry.read_info(&mut reader);
+                Ok(entry)
+            }
+            MetaFormat::Json => {
+                let meta: MetaJson = match serde_json::from_slice(&buffer) {
+                    Ok(meta) => meta,
+                    Err(err) => {
+                        return Err(log::error(format!(
+                            "Error parsing meta {}: {}",
+                            path.to_str().unwrap(),
+                            err
+                        ))
+                        .in_file(path));
+                    }
+                };
+                let hash = parse_hex(&meta.hash_value, u64::from_str_radix)
+                    .map_err(|err| err.in_file(path))?;
+                let references = meta
+                    .hash_reference_data
+                    .iter()
+                    .map(|reference| {
+                        Ok((
+                            parse_hex(&reference.hash, u64::from_str_radix)?,
+                            parse_hex(&reference.flag, u8::from_str_radix)?,
+                        ))
+                    })
+                    .collect::<log::Result<_>>()
+                    .map_err(|err| err.in_file(path))?;
+                Ok(Entry {
+                    hash,
+                    data_offset: meta.hash_offset,
+                    data_size: meta.hash_size,
+                    resource_type: meta.hash_resource_type,
+                    references,
+                    reference_count_flags: 0,
+                    states_chunk_size: meta.hash_reference_table_dummy,
+                    size: meta.hash_size_final,
+                    system_memory_requirement: meta.hash_size_in_memory,
+                    video_memory_requirement: meta.hash_size_in_video_memory,
+                    stored: None,
+                })
+            }
+        }
+    }
+
+    /// Writes the meta file next to an extracted resource.
+    pub fn write_meta(&self, resource: &Path, format: MetaFormat) -> log::Result<()> {
+        let path = format.path(resource);
+        let buffer = match format {
+            MetaFormat::Binary => {
+                let mut writer = Cursor::new(Vec::new());
+                self.write(&mut writer);
+                self.write_info(&mut writer);
+                writer.into_inner()
+            }
+            MetaFormat::Json => {
+                let meta = MetaJson {
+                    hash_value: format!("{:016X}", self.hash),
+                    hash_offset: self.data_offset,
+                    hash_size: self.data_size,
+                    hash_resource_type: self.resource_type.clone(),
+                    hash_reference_table_size: self.references_chunk_size(),
+                    hash_reference_table_dummy: self.states_chunk_size,
+                    hash_size_final: self.size,
+                    hash_size_in_memory: self.system_memory_requirement,
+                    hash_size_in_video_memory: self.video_memory_requirement,
+                    hash_reference_data: self
+                        .references
+                        .iter()
+                        .map(|(hash, flags)| ReferenceJson {
+                            hash: format!("{:016X}", hash),
+                            flag: format!("{:02X}", flags),
+                        })
+                        .collect(),
+                };
+                serde_json::to_vec_pretty(&meta).unwrap()
+            }
+        };
+        let mut file = match File::create(&path) {
+            Ok(file) => file,
+            Err(err) => {
+                return Err(log::error(format!(
+                    "Error creating file {}: {}",
+                    path.to_str().unwrap(),
+                    err
+                ))
+                .in_file(&path));
+            }
+        };
+        file.write_all(&buffer).unwrap();
+        log!("Wrote resource meta: {}", path.to_str().unwrap());
+        Ok(())
+    }
+
+    /// Converts the reference flags between the legacy and standard formats of the game versions.
+    /// The referenced hashes and their order are kept, so bone_rig_resource_index and the
+    /// material_ids of the PRIM still index the same references.
+    pub fn convert_references(
+        &mut self,
+        input_version: &GameVersion,
+        output_version: &GameVersion,
+    ) {
+        let convert = match (is_legacy(input_version), is_legacy(output_version)) {
+            (true, false) => legacy_to_standard,
+            (false, true) => standard_to_legacy,
+            _ => return,
+        };
+        for (_, flags) in &mut self.references {
+            *flags = convert(*flags);
+        }
+    }
+
+    /// Whether the reference flags are in the legacy format, as standard flags always carry a
+    /// language code.
+    pub fn has_legacy_references(&self) -> bool {
+        !self.references.is_empty()
+            && self
+                .references
+                .iter()
+                .all(|(_, flags)| flags & 0x1F != 0x1F)
+    }
+
+    /// Updates the sizes for new resource data written as is, neither scrambled nor compressed.
+    pub fn update_data(&mut self, size: u32) {
+        self.data_offset = 0;
+        self.data_size = 0;
+        self.size = size;
+        self.system_memory_requirement = size;
+    }
+}